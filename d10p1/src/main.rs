@@ -1,68 +1,368 @@
+use std::collections::BTreeSet;
+use std::env;
 use std::error::Error;
 use std::fs;
-use std::str::FromStr;
 
-enum Instruction {
-	Add(i32),
-	NoOp,
+const X: usize = 0;
+// One full frame of the CRT
+const DISASSEMBLY_CYCLE_LIMIT: u64 = 240;
+
+#[derive(Clone, Copy, Debug)]
+enum Operand {
+	Register(usize),
+	Immediate(i64),
+}
+
+struct Registers {
+	values: Vec<i64>,
 }
 
-impl FromStr for Instruction {
-	type Err = ();
+impl Registers {
+	fn value(&self, operand: &Operand) -> i64 {
+		match operand {
+			Operand::Register(index) => self.values[*index],
+			Operand::Immediate(value) => *value,
+		}
+	}
+}
+
+// Runs when the last cycle of an instruction completes. A returned offset is a jump relative to the instruction;
+// otherwise execution continues with the next instruction.
+type Execute = fn(&mut Registers, &[Operand]) -> Option<i64>;
+
+struct Operation {
+	mnemonic: &'static str,
+	operand_count: usize,
+	cycles: u32,
+	execute: Execute,
+}
+
+struct InstructionSet {
+	registers: Vec<(&'static str, i64)>,
+	operations: Vec<Operation>,
+}
+
+impl InstructionSet {
+	fn device() -> Self {
+		Self {
+			registers: vec![("x", 1)],
+			operations: vec![
+				Operation {
+					mnemonic: "noop",
+					operand_count: 0,
+					cycles: 1,
+					execute: |_, _| None,
+				},
+				Operation {
+					mnemonic: "addx",
+					operand_count: 1,
+					cycles: 2,
+					execute: |registers, operands| {
+						registers.values[X] = registers.values[X].wrapping_add(registers.value(&operands[0]));
+						None
+					},
+				},
+				Operation {
+					mnemonic: "mulx",
+					operand_count: 1,
+					cycles: 2,
+					execute: |registers, operands| {
+						registers.values[X] = registers.values[X].wrapping_mul(registers.value(&operands[0]));
+						None
+					},
+				},
+				Operation {
+					mnemonic: "jmp",
+					operand_count: 1,
+					cycles: 1,
+					execute: |registers, operands| Some(registers.value(&operands[0])),
+				},
+			],
+		}
+	}
+
+	fn register_index(&self, name: &str) -> Option<usize> {
+		self.registers
+			.iter()
+			.position(|(register_name, _)| *register_name == name)
+	}
+
+	fn parse_instruction(&self, line: &str) -> Result<Instruction, String> {
+		let mut parts = line.split_whitespace();
+		let mnemonic = parts.next().ok_or("Empty instruction")?;
+		let operation = self
+			.operations
+			.iter()
+			.position(|operation| operation.mnemonic == mnemonic)
+			.ok_or_else(|| format!("Unknown instruction: {}", mnemonic))?;
+
+		let mut operands: Vec<Operand> = Vec::new();
+		for operand in parts {
+			let operand = match self.register_index(operand) {
+				Some(index) => Operand::Register(index),
+				None => Operand::Immediate(
+					operand
+						.parse()
+						.map_err(|_| format!("Invalid operand for {}: {}", mnemonic, operand))?,
+				),
+			};
+			operands.push(operand);
+		}
+
+		let expected_operands = self.operations[operation].operand_count;
+		if operands.len() != expected_operands {
+			return Err(format!(
+				"{} takes {} operands, found {}",
+				mnemonic,
+				expected_operands,
+				operands.len()
+			));
+		}
+
+		Ok(Instruction { operation, operands })
+	}
+
+	fn describe_registers(&self, values: &[i64]) -> String {
+		let registers: Vec<String> = self
+			.registers
+			.iter()
+			.zip(values.iter())
+			.map(|((name, _), value)| format!("{}={}", name, value))
+			.collect();
+		registers.join(" ")
+	}
+
+	fn format_instruction(&self, instruction: &Instruction) -> String {
+		let mut parts: Vec<String> = vec![String::from(self.operations[instruction.operation].mnemonic)];
+		for operand in instruction.operands.iter() {
+			parts.push(match operand {
+				Operand::Register(index) => String::from(self.registers[*index].0),
+				Operand::Immediate(value) => value.to_string(),
+			});
+		}
+		parts.join(" ")
+	}
+}
+
+struct Instruction {
+	operation: usize,
+	operands: Vec<Operand>,
+}
+
+#[derive(Clone)]
+struct TraceEntry {
+	cycle: u64,
+	instruction: usize,
+	registers: Vec<i64>,
+}
+
+enum StopReason {
+	Breakpoint(u64),
+	Halted,
+}
+
+struct Cpu<'a> {
+	instruction_set: &'a InstructionSet,
+	program: &'a [Instruction],
+	registers: Registers,
+	cycle: u64,
+	next_instruction: i64,
+	in_flight: Option<(usize, u32)>,
+	breakpoints: BTreeSet<u64>,
+	stopped_at: Option<u64>,
+	trace: Option<Vec<TraceEntry>>,
+}
+
+impl<'a> Cpu<'a> {
+	fn new(instruction_set: &'a InstructionSet, program: &'a [Instruction]) -> Self {
+		let registers = Registers {
+			values: instruction_set.registers.iter().map(|(_, value)| *value).collect(),
+		};
+		Self {
+			instruction_set,
+			program,
+			registers,
+			cycle: 0,
+			next_instruction: 0,
+			in_flight: None,
+			breakpoints: BTreeSet::new(),
+			stopped_at: None,
+			trace: None,
+		}
+	}
+
+	fn register(&self, name: &str) -> Option<i64> {
+		self.instruction_set
+			.register_index(name)
+			.map(|index| self.registers.values[index])
+	}
 
-	fn from_str(s: &str) -> Result<Self, Self::Err> {
-		let mut parts = s.split(' ');
-		let instruction = parts.next().unwrap();
-		match instruction {
-			"addx" => {
-				let addend: i32 = parts.next().unwrap().parse().unwrap();
-				Ok(Self::Add(addend))
+	// Runs a single cycle, returning the register values during that cycle, or None once the program has finished.
+	fn step(&mut self) -> Option<TraceEntry> {
+		let (instruction_index, cycles_elapsed) = match self.in_flight.take() {
+			Some(in_flight) => in_flight,
+			None => {
+				let instruction_index = usize::try_from(self.next_instruction)
+					.ok()
+					.filter(|index| *index < self.program.len())?;
+				(instruction_index, 0)
 			}
-			"noop" => Ok(Self::NoOp),
-			_ => unreachable!(),
+		};
+
+		self.cycle += 1;
+		let entry = TraceEntry {
+			cycle: self.cycle,
+			instruction: instruction_index,
+			registers: self.registers.values.clone(),
+		};
+
+		let instruction = &self.program[instruction_index];
+		let operation = &self.instruction_set.operations[instruction.operation];
+		if cycles_elapsed + 1 < operation.cycles {
+			self.in_flight = Some((instruction_index, cycles_elapsed + 1));
+		} else {
+			let jump = (operation.execute)(&mut self.registers, &instruction.operands);
+			self.next_instruction = instruction_index as i64 + jump.unwrap_or(1);
 		}
+
+		if let Some(trace) = self.trace.as_mut() {
+			trace.push(entry.clone());
+		}
+		Some(entry)
+	}
+
+	// Runs until the program finishes or the next cycle has a breakpoint on it. When stopped at a breakpoint, the
+	// registers hold their values during the breakpoint's cycle.
+	fn run(&mut self) -> StopReason {
+		loop {
+			let next_cycle = self.cycle + 1;
+			if self.breakpoints.contains(&next_cycle) && self.stopped_at != Some(next_cycle) {
+				self.stopped_at = Some(next_cycle);
+				return StopReason::Breakpoint(next_cycle);
+			}
+			if self.step().is_none() {
+				return StopReason::Halted;
+			}
+		}
+	}
+}
+
+// Lists the instructions in the order they execute, up to the cycle limit, with the cycles each one occupied and
+// the register values before and after it
+fn disassemble(instruction_set: &InstructionSet, program: &[Instruction], cycle_limit: u64) -> Vec<String> {
+	let mut cpu = Cpu::new(instruction_set, program);
+	let mut listing: Vec<String> = Vec::new();
+	let mut instruction_start: Option<TraceEntry> = None;
+	while cpu.cycle < cycle_limit {
+		let Some(entry) = cpu.step() else {
+			break;
+		};
+		let first_cycle = instruction_start.take().unwrap_or_else(|| entry.clone());
+		if cpu.in_flight.is_some() {
+			instruction_start = Some(first_cycle);
+			continue;
+		}
+
+		let cycles = if first_cycle.cycle == entry.cycle {
+			entry.cycle.to_string()
+		} else {
+			format!("{}-{}", first_cycle.cycle, entry.cycle)
+		};
+		listing.push(format!(
+			"{:>9}  {:>4}  {:<12} {} -> {}",
+			cycles,
+			entry.instruction,
+			instruction_set.format_instruction(&program[entry.instruction]),
+			instruction_set.describe_registers(&first_cycle.registers),
+			instruction_set.describe_registers(&cpu.registers.values)
+		));
+	}
+	listing
+}
+
+fn parse_cycle_list(list: &str) -> Result<Vec<u64>, Box<dyn Error>> {
+	let mut cycles: Vec<u64> = Vec::new();
+	for cycle in list.split(',') {
+		cycles.push(cycle.trim().parse()?);
 	}
+	Ok(cycles)
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
+	let mut checkpoints: Vec<u64> = vec![20, 60, 100, 140, 180, 220];
+	let mut breakpoints: Vec<u64> = Vec::new();
+	let mut trace = false;
+	let mut disassemble_program = false;
+	let mut args = env::args().skip(1);
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--checkpoints" => {
+				checkpoints = parse_cycle_list(&args.next().ok_or("--checkpoints requires a cycle list")?)?
+			}
+			"--break" => breakpoints.extend(parse_cycle_list(&args.next().ok_or("--break requires a cycle list")?)?),
+			"--trace" => trace = true,
+			"--disassemble" => disassemble_program = true,
+			_ => return Err(format!("unknown argument: {}", arg).into()),
+		}
+	}
+
+	let instruction_set = InstructionSet::device();
 	let instructions: Vec<Instruction> = {
 		let input_string = fs::read_to_string("input.txt")?;
-		input_string.lines().map(|s| s.parse().unwrap()).collect()
+		let mut instructions: Vec<Instruction> = Vec::new();
+		for line in input_string.lines().filter(|s| !s.is_empty()) {
+			instructions.push(instruction_set.parse_instruction(line)?);
+		}
+		instructions
 	};
 
-	let mut x = 1;
-	let mut mid_cycle_addend: Option<i32> = None;
-	let mut cycle_number = 0;
-	let mut instruction_iter = instructions.iter();
-	let mut waypoint_sum = 0;
+	if disassemble_program {
+		for line in disassemble(&instruction_set, &instructions, DISASSEMBLY_CYCLE_LIMIT) {
+			println!("{}", line);
+		}
+		return Ok(());
+	}
+
+	let mut cpu = Cpu::new(&instruction_set, &instructions);
+	cpu.breakpoints.extend(checkpoints.iter().copied());
+	cpu.breakpoints.extend(breakpoints.iter().copied());
+	if trace {
+		cpu.trace = Some(Vec::new());
+	}
 
-	loop {
-		cycle_number += 1;
-		if cycle_number == 20
-			|| cycle_number == 60
-			|| cycle_number == 100
-			|| cycle_number == 140
-			|| cycle_number == 180
-			|| cycle_number == 220
-		{
+	// Programs can keep running long after the last cycle of interest (or loop forever), so stop there
+	let last_stop = checkpoints.iter().chain(breakpoints.iter()).copied().max();
+	let mut waypoint_sum = 0;
+	while let StopReason::Breakpoint(cycle_number) = cpu.run() {
+		if breakpoints.contains(&cycle_number) {
+			println!(
+				"break at cycle {}: {}",
+				cycle_number,
+				instruction_set.describe_registers(&cpu.registers.values)
+			);
+		}
+		if checkpoints.contains(&cycle_number) {
+			let x = cpu.register("x").unwrap();
+			let cycle_number = cycle_number as i64;
 			waypoint_sum += x * cycle_number;
 			println!("{} * {} = {}", x, cycle_number, x * cycle_number);
-			if cycle_number == 220 {
-				break;
-			}
 		}
-		if let Some(addend) = mid_cycle_addend.take() {
-			x += addend;
-		} else {
-			let Some(next_instruction) = instruction_iter.next() else {
-				break;
-			};
-			match next_instruction {
-				Instruction::Add(num) => mid_cycle_addend = Some(*num),
-				Instruction::NoOp => (),
-			}
+		if Some(cycle_number) == last_stop {
+			break;
 		}
 	}
+
+	if let Some(trace) = cpu.trace.as_ref() {
+		for entry in trace.iter() {
+			println!(
+				"{} [{}]: {}",
+				entry.cycle,
+				entry.instruction,
+				instruction_set.describe_registers(&entry.registers)
+			);
+		}
+	}
+
 	println!("{}", waypoint_sum);
 
 	Ok(())
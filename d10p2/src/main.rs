@@ -1,64 +1,945 @@
+use std::collections::BTreeSet;
+use std::env;
 use std::error::Error;
-use std::fs;
-use std::str::FromStr;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
 
-enum Instruction {
-	Add(i32),
-	NoOp,
+const X: usize = 0;
+// One full frame of the CRT. Nothing a program does after it can change the picture.
+const FRAME_CYCLES: u64 = (CRT_WIDTH * CRT_HEIGHT) as u64;
+
+#[derive(Clone, Copy, Debug)]
+enum Operand {
+	Register(usize),
+	Immediate(i64),
 }
 
-impl FromStr for Instruction {
-	type Err = ();
+struct Registers {
+	values: Vec<i64>,
+}
 
-	fn from_str(s: &str) -> Result<Self, Self::Err> {
-		let mut parts = s.split(' ');
-		let instruction = parts.next().unwrap();
-		match instruction {
-			"addx" => {
-				let addend: i32 = parts.next().unwrap().parse().unwrap();
-				Ok(Self::Add(addend))
-			}
-			"noop" => Ok(Self::NoOp),
-			_ => unreachable!(),
+impl Registers {
+	fn value(&self, operand: &Operand) -> i64 {
+		match operand {
+			Operand::Register(index) => self.values[*index],
+			Operand::Immediate(value) => *value,
 		}
 	}
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-	let instructions: Vec<Instruction> = {
-		let input_string = fs::read_to_string("input.txt")?;
-		input_string.lines().map(|s| s.parse().unwrap()).collect()
-	};
+// Runs when the last cycle of an instruction completes. A returned offset is a jump relative to the instruction;
+// otherwise execution continues with the next instruction.
+type Execute = fn(&mut Registers, &[Operand]) -> Option<i64>;
+
+struct Operation {
+	mnemonic: &'static str,
+	operand_count: usize,
+	cycles: u32,
+	execute: Execute,
+}
+
+struct InstructionSet {
+	registers: Vec<(&'static str, i64)>,
+	operations: Vec<Operation>,
+}
+
+impl InstructionSet {
+	fn device() -> Self {
+		Self {
+			registers: vec![("x", 1)],
+			operations: vec![
+				Operation {
+					mnemonic: "noop",
+					operand_count: 0,
+					cycles: 1,
+					execute: |_, _| None,
+				},
+				Operation {
+					mnemonic: "addx",
+					operand_count: 1,
+					cycles: 2,
+					execute: |registers, operands| {
+						registers.values[X] = registers.values[X].wrapping_add(registers.value(&operands[0]));
+						None
+					},
+				},
+				Operation {
+					mnemonic: "mulx",
+					operand_count: 1,
+					cycles: 2,
+					execute: |registers, operands| {
+						registers.values[X] = registers.values[X].wrapping_mul(registers.value(&operands[0]));
+						None
+					},
+				},
+				Operation {
+					mnemonic: "jmp",
+					operand_count: 1,
+					cycles: 1,
+					execute: |registers, operands| Some(registers.value(&operands[0])),
+				},
+			],
+		}
+	}
+
+	fn operation_index(&self, mnemonic: &str) -> Option<usize> {
+		self.operations
+			.iter()
+			.position(|operation| operation.mnemonic == mnemonic)
+	}
+
+	fn register_index(&self, name: &str) -> Option<usize> {
+		self.registers
+			.iter()
+			.position(|(register_name, _)| *register_name == name)
+	}
+
+	fn parse_instruction(&self, line: &str) -> Result<Instruction, String> {
+		let mut parts = line.split_whitespace();
+		let mnemonic = parts.next().ok_or("Empty instruction")?;
+		let operation = self
+			.operation_index(mnemonic)
+			.ok_or_else(|| format!("Unknown instruction: {}", mnemonic))?;
+
+		let mut operands: Vec<Operand> = Vec::new();
+		for operand in parts {
+			let operand = match self.register_index(operand) {
+				Some(index) => Operand::Register(index),
+				None => Operand::Immediate(
+					operand
+						.parse()
+						.map_err(|_| format!("Invalid operand for {}: {}", mnemonic, operand))?,
+				),
+			};
+			operands.push(operand);
+		}
+
+		let expected_operands = self.operations[operation].operand_count;
+		if operands.len() != expected_operands {
+			return Err(format!(
+				"{} takes {} operands, found {}",
+				mnemonic,
+				expected_operands,
+				operands.len()
+			));
+		}
+
+		Ok(Instruction { operation, operands })
+	}
+
+	fn describe_registers(&self, values: &[i64]) -> String {
+		let registers: Vec<String> = self
+			.registers
+			.iter()
+			.zip(values.iter())
+			.map(|((name, _), value)| format!("{}={}", name, value))
+			.collect();
+		registers.join(" ")
+	}
+
+	fn format_instruction(&self, instruction: &Instruction) -> String {
+		let mut parts: Vec<String> = vec![String::from(self.operations[instruction.operation].mnemonic)];
+		for operand in instruction.operands.iter() {
+			parts.push(match operand {
+				Operand::Register(index) => String::from(self.registers[*index].0),
+				Operand::Immediate(value) => value.to_string(),
+			});
+		}
+		parts.join(" ")
+	}
+}
+
+struct Instruction {
+	operation: usize,
+	operands: Vec<Operand>,
+}
+
+#[derive(Clone)]
+struct TraceEntry {
+	cycle: u64,
+	instruction: usize,
+	registers: Vec<i64>,
+}
+
+enum StopReason {
+	Breakpoint(u64),
+	CycleLimit,
+	Halted,
+}
+
+struct Cpu<'a> {
+	instruction_set: &'a InstructionSet,
+	program: &'a [Instruction],
+	registers: Registers,
+	cycle: u64,
+	next_instruction: i64,
+	in_flight: Option<(usize, u32)>,
+	breakpoints: BTreeSet<u64>,
+	stopped_at: Option<u64>,
+	cycle_limit: Option<u64>,
+	trace: Option<Vec<TraceEntry>>,
+}
+
+impl<'a> Cpu<'a> {
+	fn new(instruction_set: &'a InstructionSet, program: &'a [Instruction]) -> Self {
+		let registers = Registers {
+			values: instruction_set.registers.iter().map(|(_, value)| *value).collect(),
+		};
+		Self {
+			instruction_set,
+			program,
+			registers,
+			cycle: 0,
+			next_instruction: 0,
+			in_flight: None,
+			breakpoints: BTreeSet::new(),
+			stopped_at: None,
+			cycle_limit: None,
+			trace: None,
+		}
+	}
+
+	// Runs a single cycle, returning the register values during that cycle, or None once the program has finished.
+	fn step(&mut self) -> Option<TraceEntry> {
+		let (instruction_index, cycles_elapsed) = match self.in_flight.take() {
+			Some(in_flight) => in_flight,
+			None => {
+				let instruction_index = usize::try_from(self.next_instruction)
+					.ok()
+					.filter(|index| *index < self.program.len())?;
+				(instruction_index, 0)
+			}
+		};
+
+		self.cycle += 1;
+		let entry = TraceEntry {
+			cycle: self.cycle,
+			instruction: instruction_index,
+			registers: self.registers.values.clone(),
+		};
 
-	let mut x: i32 = 1;
-	let mut mid_cycle_addend: Option<i32> = None;
-	let mut cycle_number = 0;
-	let mut instruction_iter = instructions.iter();
+		let instruction = &self.program[instruction_index];
+		let operation = &self.instruction_set.operations[instruction.operation];
+		if cycles_elapsed + 1 < operation.cycles {
+			self.in_flight = Some((instruction_index, cycles_elapsed + 1));
+		} else {
+			let jump = (operation.execute)(&mut self.registers, &instruction.operands);
+			self.next_instruction = instruction_index as i64 + jump.unwrap_or(1);
+		}
+
+		if let Some(trace) = self.trace.as_mut() {
+			trace.push(entry.clone());
+		}
+		Some(entry)
+	}
 
-	loop {
-		cycle_number += 1;
+	// Runs until the program finishes, the cycle limit is reached or the next cycle has a breakpoint on it. When
+	// stopped at a breakpoint, the registers hold their values during the breakpoint's cycle.
+	fn run(&mut self) -> StopReason {
+		loop {
+			if self.cycle_limit.is_some_and(|limit| self.cycle >= limit) {
+				return StopReason::CycleLimit;
+			}
+			let next_cycle = self.cycle + 1;
+			if self.breakpoints.contains(&next_cycle) && self.stopped_at != Some(next_cycle) {
+				self.stopped_at = Some(next_cycle);
+				return StopReason::Breakpoint(next_cycle);
+			}
+			if self.step().is_none() {
+				return StopReason::Halted;
+			}
+		}
+	}
+}
 
-		if let Some(addend) = mid_cycle_addend.take() {
-			x += addend;
+// Lists the instructions in the order they execute, up to the cycle limit, with the cycles each one occupied and
+// the register values before and after it
+fn disassemble(instruction_set: &InstructionSet, program: &[Instruction], cycle_limit: u64) -> Vec<String> {
+	let mut cpu = Cpu::new(instruction_set, program);
+	let mut listing: Vec<String> = Vec::new();
+	let mut instruction_start: Option<TraceEntry> = None;
+	while cpu.cycle < cycle_limit {
+		let Some(entry) = cpu.step() else {
+			break;
+		};
+		let first_cycle = instruction_start.take().unwrap_or_else(|| entry.clone());
+		if cpu.in_flight.is_some() {
+			instruction_start = Some(first_cycle);
+			continue;
+		}
+
+		let cycles = if first_cycle.cycle == entry.cycle {
+			entry.cycle.to_string()
 		} else {
-			let Some(next_instruction) = instruction_iter.next() else {
-				break;
+			format!("{}-{}", first_cycle.cycle, entry.cycle)
+		};
+		listing.push(format!(
+			"{:>9}  {:>4}  {:<12} {} -> {}",
+			cycles,
+			entry.instruction,
+			instruction_set.format_instruction(&program[entry.instruction]),
+			instruction_set.describe_registers(&first_cycle.registers),
+			instruction_set.describe_registers(&cpu.registers.values)
+		));
+	}
+	listing
+}
+
+fn parse_cycle_list(list: &str) -> Result<Vec<u64>, Box<dyn Error>> {
+	let mut cycles: Vec<u64> = Vec::new();
+	for cycle in list.split(',') {
+		cycles.push(cycle.trim().parse()?);
+	}
+	Ok(cycles)
+}
+
+const CRT_WIDTH: usize = 40;
+const CRT_HEIGHT: usize = 6;
+const GLYPH_WIDTH: usize = 4;
+const GLYPH_SPACING: usize = 5;
+// Sprite positions outside this range can't light any pixel, so they're all equivalent to the ends of the range
+const MIN_SPRITE_X: i64 = -2;
+const MAX_SPRITE_X: i64 = CRT_WIDTH as i64 + 1;
+
+const FONT: [(char, [&str; CRT_HEIGHT]); 19] = [
+	(' ', ["....", "....", "....", "....", "....", "...."]),
+	('A', [".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+	('B', ["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+	('C', [".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+	('E', ["####", "#...", "###.", "#...", "#...", "####"]),
+	('F', ["####", "#...", "###.", "#...", "#...", "#..."]),
+	('G', [".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+	('H', ["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+	('I', [".###", "..#.", "..#.", "..#.", "..#.", ".###"]),
+	('J', ["..##", "...#", "...#", "...#", "#..#", ".##."]),
+	('K', ["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+	('L', ["#...", "#...", "#...", "#...", "#...", "####"]),
+	('O', [".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+	('P', ["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+	('R', ["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+	('S', [".###", "#...", "#...", ".##.", "...#", "###."]),
+	('U', ["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+	('Y', ["#...", "#...", ".#.#", "..#.", "..#.", "..#."]),
+	('Z', ["####", "...#", "..#.", ".#..", "#...", "####"]),
+];
+
+#[derive(Eq, PartialEq)]
+struct Framebuffer {
+	pixels: [[bool; CRT_WIDTH]; CRT_HEIGHT],
+}
+
+impl Framebuffer {
+	fn from_trace(trace: &[TraceEntry]) -> Self {
+		let mut pixels = [[false; CRT_WIDTH]; CRT_HEIGHT];
+		for entry in trace.iter().take(CRT_WIDTH * CRT_HEIGHT) {
+			let pixel_index = (entry.cycle - 1) as usize;
+			let row = pixel_index / CRT_WIDTH;
+			let col = pixel_index % CRT_WIDTH;
+			pixels[row][col] = entry.registers[X].abs_diff(col as i64) <= 1;
+		}
+		Self { pixels }
+	}
+
+	fn parse(bitmap: &str) -> Result<Self, String> {
+		let rows: Vec<&str> = bitmap.lines().filter(|line| !line.is_empty()).collect();
+		if rows.len() != CRT_HEIGHT {
+			return Err(format!(
+				"Expected {} rows in the bitmap, found {}",
+				CRT_HEIGHT,
+				rows.len()
+			));
+		}
+
+		let mut pixels = [[false; CRT_WIDTH]; CRT_HEIGHT];
+		for (row_index, row) in rows.iter().enumerate() {
+			if row.chars().count() != CRT_WIDTH {
+				return Err(format!("Bitmap row {} is not {} pixels wide", row_index, CRT_WIDTH));
+			}
+			for (col_index, c) in row.chars().enumerate() {
+				pixels[row_index][col_index] = match c {
+					'#' => true,
+					'.' => false,
+					_ => return Err(format!("Unexpected bitmap pixel: {}", c)),
+				};
+			}
+		}
+		Ok(Self { pixels })
+	}
+
+	fn from_text(text: &str) -> Result<Self, String> {
+		if text.chars().count() > CRT_WIDTH / GLYPH_SPACING {
+			return Err(format!(
+				"The CRT only fits {} letters: {}",
+				CRT_WIDTH / GLYPH_SPACING,
+				text
+			));
+		}
+
+		let mut pixels = [[false; CRT_WIDTH]; CRT_HEIGHT];
+		for (glyph_index, letter) in text.chars().enumerate() {
+			if letter == ' ' {
+				continue;
+			}
+			let (_, glyph) = FONT
+				.iter()
+				.find(|(font_letter, _)| *font_letter == letter.to_ascii_uppercase())
+				.ok_or_else(|| format!("No glyph for letter: {}", letter))?;
+			let left = glyph_index * GLYPH_SPACING;
+			for (pixel_row, glyph_row) in pixels.iter_mut().zip(glyph.iter()) {
+				for (pixel, glyph_pixel) in pixel_row[left..left + GLYPH_WIDTH].iter_mut().zip(glyph_row.chars()) {
+					*pixel = glyph_pixel == '#';
+				}
+			}
+		}
+		Ok(Self { pixels })
+	}
+
+	// Whether drawing during the given cycle with the sprite at x gives the right pixel. Cycles after the end of the
+	// frame don't draw anything, so anything goes.
+	fn sprite_fits(&self, cycle: usize, x: i64) -> bool {
+		if cycle > CRT_WIDTH * CRT_HEIGHT {
+			return true;
+		}
+		let pixel_index = cycle - 1;
+		let col = pixel_index % CRT_WIDTH;
+		self.pixels[pixel_index / CRT_WIDTH][col] == (x.abs_diff(col as i64) <= 1)
+	}
+
+	fn render(&self) -> String {
+		let mut output = String::new();
+		for row in self.pixels.iter() {
+			for pixel in row.iter() {
+				output.push(if *pixel { '#' } else { '.' });
+			}
+			output.push('\n');
+		}
+		output
+	}
+
+	fn read_glyph(&self, glyph_index: usize) -> Option<char> {
+		let left = glyph_index * GLYPH_SPACING;
+		FONT.iter()
+			.find(|(_, glyph)| {
+				glyph.iter().zip(self.pixels.iter()).all(|(glyph_row, pixel_row)| {
+					glyph_row
+						.chars()
+						.zip(pixel_row[left..left + GLYPH_WIDTH].iter())
+						.all(|(glyph_pixel, pixel)| (glyph_pixel == '#') == *pixel)
+				})
+			})
+			.map(|(letter, _)| *letter)
+	}
+
+	// Unrecognised glyphs are read as '?'
+	fn read_letters(&self) -> String {
+		(0..CRT_WIDTH / GLYPH_SPACING)
+			.map(|glyph_index| self.read_glyph(glyph_index).unwrap_or('?'))
+			.collect()
+	}
+
+	fn scaled_rows(&self, scale: usize) -> impl Iterator<Item = Vec<bool>> + '_ {
+		self.pixels.iter().flat_map(move |row| {
+			let scaled_row: Vec<bool> = row
+				.iter()
+				.flat_map(|pixel| std::iter::repeat_n(*pixel, scale))
+				.collect();
+			std::iter::repeat_n(scaled_row, scale)
+		})
+	}
+
+	fn write_pbm(&self, path: &str, scale: usize) -> io::Result<()> {
+		let mut file = BufWriter::new(File::create(path)?);
+		writeln!(file, "P1\n{} {}", CRT_WIDTH * scale, CRT_HEIGHT * scale)?;
+		for row in self.scaled_rows(scale) {
+			let line: Vec<&str> = row.iter().map(|pixel| if *pixel { "1" } else { "0" }).collect();
+			writeln!(file, "{}", line.join(" "))?;
+		}
+		file.flush()
+	}
+
+	fn write_png(&self, path: &str, scale: usize) -> io::Result<()> {
+		let width = CRT_WIDTH * scale;
+		let height = CRT_HEIGHT * scale;
+
+		// 8-bit greyscale scanlines, each preceded by filter type 0 (none)
+		let mut image_data: Vec<u8> = Vec::with_capacity((width + 1) * height);
+		for row in self.scaled_rows(scale) {
+			image_data.push(0);
+			image_data.extend(row.iter().map(|pixel| if *pixel { 255 } else { 0 }));
+		}
+
+		let mut header: Vec<u8> = Vec::new();
+		header.extend((width as u32).to_be_bytes());
+		header.extend((height as u32).to_be_bytes());
+		header.extend([8, 0, 0, 0, 0]);
+
+		let mut file = BufWriter::new(File::create(path)?);
+		file.write_all(b"\x89PNG\r\n\x1a\n")?;
+		write_png_chunk(&mut file, b"IHDR", &header)?;
+		write_png_chunk(&mut file, b"IDAT", &zlib_stored(&image_data))?;
+		write_png_chunk(&mut file, b"IEND", &[])?;
+		file.flush()
+	}
+}
+
+// Builds the shortest addx/noop program that draws the framebuffer. The sprite can only move at the end of an addx,
+// so this searches over (cycles completed, sprite position) for the fewest instructions that get to the end of the
+// frame with every pixel drawn correctly.
+fn assemble(instruction_set: &InstructionSet, framebuffer: &Framebuffer) -> Result<Vec<Instruction>, String> {
+	let noop = instruction_set.operation_index("noop").ok_or("No noop instruction")?;
+	let addx = instruction_set.operation_index("addx").ok_or("No addx instruction")?;
+	let frame_cycles = CRT_WIDTH * CRT_HEIGHT;
+	let position_index = |x: i64| (x - MIN_SPRITE_X) as usize;
+	let start_x = instruction_set.registers[X].1.clamp(MIN_SPRITE_X, MAX_SPRITE_X);
+
+	// For each state, the fewest instructions to reach it, plus the previous sprite position and the cycles taken by
+	// the instruction that got there. An addx can finish one cycle after the frame ends.
+	let mut fewest_instructions: Vec<Vec<Option<(usize, i64, usize)>>> =
+		vec![vec![None; position_index(MAX_SPRITE_X) + 1]; frame_cycles + 2];
+	fewest_instructions[0][position_index(start_x)] = Some((0, start_x, 0));
+	let mut furthest_cycle = 0;
+	for cycles_completed in 0..frame_cycles {
+		for x in MIN_SPRITE_X..=MAX_SPRITE_X {
+			let Some((instruction_count, _, _)) = fewest_instructions[cycles_completed][position_index(x)] else {
+				continue;
 			};
-			match next_instruction {
-				Instruction::Add(num) => mid_cycle_addend = Some(*num),
-				Instruction::NoOp => (),
+			if !framebuffer.sprite_fits(cycles_completed + 1, x) {
+				continue;
+			}
+			furthest_cycle = furthest_cycle.max(cycles_completed + 1);
+
+			let mut next_states = vec![(cycles_completed + 1, x)];
+			if framebuffer.sprite_fits(cycles_completed + 2, x) {
+				next_states.extend(
+					(MIN_SPRITE_X..=MAX_SPRITE_X)
+						.filter(|new_x| *new_x != x)
+						.map(|new_x| (cycles_completed + 2, new_x)),
+				);
+			}
+			for (next_cycles_completed, next_x) in next_states {
+				let next_state = &mut fewest_instructions[next_cycles_completed][position_index(next_x)];
+				if next_state.is_none_or(|(count, _, _)| instruction_count + 1 < count) {
+					*next_state = Some((instruction_count + 1, x, next_cycles_completed - cycles_completed));
+				}
+			}
+		}
+	}
+
+	let mut end_state: Option<(usize, usize, i64)> = None;
+	for cycles_completed in [frame_cycles, frame_cycles + 1] {
+		for x in MIN_SPRITE_X..=MAX_SPRITE_X {
+			if let Some((count, _, _)) = fewest_instructions[cycles_completed][position_index(x)] {
+				if end_state.is_none_or(|(best_count, _, _)| count < best_count) {
+					end_state = Some((count, cycles_completed, x));
+				}
 			}
 		}
+	}
+	let Some((_, mut cycles_completed, mut x)) = end_state else {
+		return Err(format!(
+			"No program can draw the pixel at row {}, col {}",
+			furthest_cycle / CRT_WIDTH,
+			furthest_cycle % CRT_WIDTH
+		));
+	};
 
-		if x.abs_diff(cycle_number % 40) <= 1 {
-			print!("#");
+	let mut program: Vec<Instruction> = Vec::new();
+	while cycles_completed > 0 {
+		let (_, previous_x, cycles) = fewest_instructions[cycles_completed][position_index(x)].unwrap();
+		program.push(if cycles == 1 {
+			Instruction {
+				operation: noop,
+				operands: Vec::new(),
+			}
 		} else {
-			print!(".");
+			Instruction {
+				operation: addx,
+				operands: vec![Operand::Immediate(x - previous_x)],
+			}
+		});
+		cycles_completed -= cycles;
+		x = previous_x;
+	}
+	program.reverse();
+	Ok(program)
+}
+
+fn write_png_chunk<W: Write>(writer: &mut W, chunk_type: &[u8; 4], data: &[u8]) -> io::Result<()> {
+	writer.write_all(&(data.len() as u32).to_be_bytes())?;
+	writer.write_all(chunk_type)?;
+	writer.write_all(data)?;
+	let checksum = crc32(chunk_type.iter().chain(data.iter()));
+	writer.write_all(&checksum.to_be_bytes())
+}
+
+fn crc32<'a, I: Iterator<Item = &'a u8>>(data: I) -> u32 {
+	let mut crc = 0xffff_ffffu32;
+	for byte in data {
+		crc ^= *byte as u32;
+		for _ in 0..8 {
+			crc = if crc & 1 == 1 {
+				(crc >> 1) ^ 0xedb8_8320
+			} else {
+				crc >> 1
+			};
 		}
-		if cycle_number % 40 == 0 {
-			println!();
+	}
+	!crc
+}
+
+// Wraps the data in a zlib stream of uncompressed deflate blocks; the images are small enough not to need compression
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+	let mut stream: Vec<u8> = vec![0x78, 0x01];
+	let mut blocks = data.chunks(u16::MAX as usize).peekable();
+	if blocks.peek().is_none() {
+		stream.extend([1, 0, 0, 0xff, 0xff]);
+	}
+	while let Some(block) = blocks.next() {
+		let final_block = blocks.peek().is_none();
+		let length = block.len() as u16;
+		stream.push(u8::from(final_block));
+		stream.extend(length.to_le_bytes());
+		stream.extend((!length).to_le_bytes());
+		stream.extend(block);
+	}
+
+	let mut a: u32 = 1;
+	let mut b: u32 = 0;
+	for byte in data.iter() {
+		a = (a + *byte as u32) % 65521;
+		b = (b + a) % 65521;
+	}
+	stream.extend(((b << 16) | a).to_be_bytes());
+	stream
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+	let mut breakpoints: Vec<u64> = Vec::new();
+	let mut trace = false;
+	let mut disassemble_program = false;
+	let mut assemble_from: Option<Framebuffer> = None;
+	let mut pbm_path: Option<String> = None;
+	let mut png_path: Option<String> = None;
+	let mut scale: usize = 1;
+	let mut args = env::args().skip(1);
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--break" => breakpoints.extend(parse_cycle_list(&args.next().ok_or("--break requires a cycle list")?)?),
+			"--trace" => trace = true,
+			"--disassemble" => disassemble_program = true,
+			"--assemble-bitmap" => {
+				let bitmap = fs::read_to_string(args.next().ok_or("--assemble-bitmap requires a bitmap path")?)?;
+				assemble_from = Some(Framebuffer::parse(&bitmap)?);
+			}
+			"--assemble-text" => {
+				let text = args.next().ok_or("--assemble-text requires the text to draw")?;
+				assemble_from = Some(Framebuffer::from_text(&text)?);
+			}
+			"--pbm" => pbm_path = Some(args.next().ok_or("--pbm requires an output path")?),
+			"--png" => png_path = Some(args.next().ok_or("--png requires an output path")?),
+			"--scale" => scale = args.next().ok_or("--scale requires a pixel size")?.parse()?,
+			_ => return Err(format!("unknown argument: {}", arg).into()),
+		}
+	}
+
+	if scale == 0 {
+		return Err("--scale must be at least 1".into());
+	}
+
+	let instruction_set = InstructionSet::device();
+
+	if let Some(framebuffer) = assemble_from {
+		let program = assemble(&instruction_set, &framebuffer)?;
+		let mut cpu = Cpu::new(&instruction_set, &program);
+		cpu.cycle_limit = Some(FRAME_CYCLES);
+		cpu.trace = Some(Vec::new());
+		cpu.run();
+		if Framebuffer::from_trace(&cpu.trace.unwrap()) != framebuffer {
+			return Err("The assembled program doesn't draw the requested picture".into());
+		}
+
+		for instruction in program.iter() {
+			println!("{}", instruction_set.format_instruction(instruction));
 		}
+		return Ok(());
 	}
 
+	let instructions: Vec<Instruction> = {
+		let input_string = fs::read_to_string("input.txt")?;
+		let mut instructions: Vec<Instruction> = Vec::new();
+		for line in input_string.lines().filter(|s| !s.is_empty()) {
+			instructions.push(instruction_set.parse_instruction(line)?);
+		}
+		instructions
+	};
+
+	if disassemble_program {
+		for line in disassemble(&instruction_set, &instructions, FRAME_CYCLES) {
+			println!("{}", line);
+		}
+		return Ok(());
+	}
+
+	if let Some(cycle) = breakpoints.iter().find(|cycle| **cycle > FRAME_CYCLES) {
+		return Err(format!("Breakpoint at cycle {} is after the last cycle of the frame", cycle).into());
+	}
+
+	let mut cpu = Cpu::new(&instruction_set, &instructions);
+	cpu.breakpoints.extend(breakpoints.iter().copied());
+	cpu.cycle_limit = Some(FRAME_CYCLES);
+	cpu.trace = Some(Vec::new());
+
+	while let StopReason::Breakpoint(cycle_number) = cpu.run() {
+		println!(
+			"break at cycle {}: {}",
+			cycle_number,
+			instruction_set.describe_registers(&cpu.registers.values)
+		);
+	}
+
+	let cycle_trace = cpu.trace.take().unwrap();
+	if trace {
+		for entry in cycle_trace.iter() {
+			println!(
+				"{} [{}]: {}",
+				entry.cycle,
+				entry.instruction,
+				instruction_set.describe_registers(&entry.registers)
+			);
+		}
+	}
+
+	let framebuffer = Framebuffer::from_trace(&cycle_trace);
+	if let Some(path) = pbm_path {
+		framebuffer.write_pbm(&path, scale)?;
+	}
+	if let Some(path) = png_path {
+		framebuffer.write_png(&path, scale)?;
+	}
+
+	print!("{}", framebuffer.render());
+	println!("{}", framebuffer.read_letters());
+
 	Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn parse_program(instruction_set: &InstructionSet, lines: &[&str]) -> Vec<Instruction> {
+		lines
+			.iter()
+			.map(|line| instruction_set.parse_instruction(line).unwrap())
+			.collect()
+	}
+
+	#[test]
+	fn endless_program_stops_at_cycle_limit() {
+		let instruction_set = InstructionSet::device();
+		let program = parse_program(&instruction_set, &["noop", "jmp 0"]);
+		let mut cpu = Cpu::new(&instruction_set, &program);
+		cpu.breakpoints.insert(100);
+		cpu.cycle_limit = Some(FRAME_CYCLES);
+		cpu.trace = Some(Vec::new());
+		assert!(matches!(cpu.run(), StopReason::Breakpoint(100)));
+		assert!(matches!(cpu.run(), StopReason::CycleLimit));
+		assert_eq!(cpu.trace.unwrap().len() as u64, FRAME_CYCLES);
+	}
+
+	#[test]
+	fn glyphs_read_back() {
+		let slots = CRT_WIDTH / GLYPH_SPACING;
+		for (letter, _) in FONT.iter() {
+			for glyph_index in 0..slots {
+				let text: String = (0..slots)
+					.map(|index| if index == glyph_index { *letter } else { ' ' })
+					.collect();
+				assert_eq!(Framebuffer::from_text(&text).unwrap().read_letters(), text);
+			}
+		}
+		assert_eq!(Framebuffer::from_text("rgbzjl").unwrap().read_letters(), "RGBZJL  ");
+	}
+
+	#[test]
+	fn assembled_text_reads_back() {
+		let instruction_set = InstructionSet::device();
+		let framebuffer = Framebuffer::from_text("PZGPKPEB").unwrap();
+		let program = assemble(&instruction_set, &framebuffer).unwrap();
+		let mut cpu = Cpu::new(&instruction_set, &program);
+		cpu.cycle_limit = Some(FRAME_CYCLES);
+		cpu.trace = Some(Vec::new());
+		cpu.run();
+		assert_eq!(Framebuffer::from_trace(&cpu.trace.unwrap()).read_letters(), "PZGPKPEB");
+	}
+
+	const SAMPLE_PROGRAM: &str = "addx 15
+addx -11
+addx 6
+addx -3
+addx 5
+addx -1
+addx -8
+addx 13
+addx 4
+noop
+addx -1
+addx 5
+addx -1
+addx 5
+addx -1
+addx 5
+addx -1
+addx 5
+addx -1
+addx -35
+addx 1
+addx 24
+addx -19
+addx 1
+addx 16
+addx -11
+noop
+noop
+addx 21
+addx -15
+noop
+noop
+addx -3
+addx 9
+addx 1
+addx -3
+addx 8
+addx 1
+addx 5
+noop
+noop
+noop
+noop
+noop
+addx -36
+noop
+addx 1
+addx 7
+noop
+noop
+noop
+addx 2
+addx 6
+noop
+noop
+noop
+noop
+noop
+addx 1
+noop
+noop
+addx 7
+addx 1
+noop
+addx -13
+addx 13
+addx 7
+noop
+addx 1
+addx -33
+noop
+noop
+noop
+addx 2
+noop
+noop
+noop
+addx 8
+noop
+addx -1
+addx 2
+addx 1
+noop
+addx 17
+addx -9
+addx 1
+addx 1
+addx -3
+addx 11
+noop
+noop
+addx 1
+noop
+addx 1
+noop
+noop
+addx -13
+addx -19
+addx 1
+addx 3
+addx 26
+addx -30
+addx 12
+addx -1
+addx 3
+addx 1
+noop
+noop
+noop
+addx -9
+addx 18
+addx 1
+addx 2
+noop
+noop
+addx 9
+noop
+noop
+noop
+addx -1
+addx 2
+addx -37
+addx 1
+addx 3
+noop
+addx 15
+addx -21
+addx 22
+addx -6
+addx 1
+noop
+addx 2
+addx 1
+noop
+addx -10
+noop
+noop
+addx 20
+addx 1
+addx 2
+addx 2
+addx -6
+addx -11
+noop
+noop
+noop";
+
+	const SAMPLE_CRT: &str = "\
+##..##..##..##..##..##..##..##..##..##..
+###...###...###...###...###...###...###.
+####....####....####....####....####....
+#####.....#####.....#####.....#####.....
+######......######......######......####
+#######.......#######.......#######.....
+";
+
+	#[test]
+	fn sample_program_draws_sample_crt() {
+		let instruction_set = InstructionSet::device();
+		let program = parse_program(&instruction_set, &SAMPLE_PROGRAM.lines().collect::<Vec<_>>());
+		let mut cpu = Cpu::new(&instruction_set, &program);
+		cpu.cycle_limit = Some(FRAME_CYCLES);
+		cpu.trace = Some(Vec::new());
+		cpu.run();
+		let framebuffer = Framebuffer::from_trace(&cpu.trace.unwrap());
+		assert_eq!(framebuffer.render(), SAMPLE_CRT);
+		assert!(framebuffer == Framebuffer::parse(SAMPLE_CRT).unwrap());
+		assert_eq!(framebuffer.read_letters(), "????????");
+	}
+}
@@ -1,48 +1,195 @@
 use std::collections::BTreeMap;
 use std::error::Error;
 use std::fs;
+use std::str::FromStr;
 
-#[derive(Clone, Copy)]
-enum OperationType {
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Operator {
 	Add,
+	Subtract,
 	Multiply,
+	Divide,
+	Remainder,
 }
 
-impl OperationType {
-	fn apply(&self, operand1: u32, operand2: u32) -> u32 {
-		match self {
-			Self::Add => operand1 + operand2,
-			Self::Multiply => operand1 * operand2,
+impl Operator {
+	fn from_char(c: char) -> Option<Self> {
+		match c {
+			'+' => Some(Self::Add),
+			'-' => Some(Self::Subtract),
+			'*' => Some(Self::Multiply),
+			'/' => Some(Self::Divide),
+			'%' => Some(Self::Remainder),
+			_ => None,
 		}
 	}
 }
 
-#[derive(Clone, Copy)]
-enum OperationBy {
-	OldValue,
-	Number(u32),
+#[derive(Clone, Debug)]
+enum Expression {
+	Old,
+	Number(u64),
+	Binary(Box<Expression>, Operator, Box<Expression>),
+}
+
+#[derive(Debug, Eq, PartialEq)]
+enum Token {
+	Old,
+	Number(u64),
+	Operator(Operator),
+	OpenParen,
+	CloseParen,
+}
+
+fn tokenize(s: &str) -> Result<Vec<(usize, Token)>, String> {
+	let mut tokens: Vec<(usize, Token)> = Vec::new();
+	let mut chars = s.char_indices().peekable();
+	while let Some((offset, c)) = chars.next() {
+		let token = if c.is_whitespace() {
+			continue;
+		} else if c == '(' {
+			Token::OpenParen
+		} else if c == ')' {
+			Token::CloseParen
+		} else if let Some(operator) = Operator::from_char(c) {
+			Token::Operator(operator)
+		} else if c.is_ascii_digit() {
+			let mut number = String::from(c);
+			while let Some((_, digit)) = chars.next_if(|(_, c)| c.is_ascii_digit()) {
+				number.push(digit);
+			}
+			Token::Number(
+				number
+					.parse()
+					.map_err(|_| format!("Number too large at offset {}: {}", offset, number))?,
+			)
+		} else if c.is_ascii_alphabetic() {
+			let mut word = String::from(c);
+			while let Some((_, letter)) = chars.next_if(|(_, c)| c.is_ascii_alphanumeric()) {
+				word.push(letter);
+			}
+			if word != "old" {
+				return Err(format!("Unknown name at offset {}: {}", offset, word));
+			}
+			Token::Old
+		} else {
+			return Err(format!("Unexpected character at offset {}: {}", offset, c));
+		};
+		tokens.push((offset, token));
+	}
+	Ok(tokens)
+}
+
+// Recursive descent over the usual precedence levels: + and - bind loosest, then *, / and %, all left associative
+struct ExpressionParser {
+	tokens: Vec<(usize, Token)>,
+	position: usize,
+	end_offset: usize,
 }
 
-#[derive(Clone, Copy)]
-struct Operation {
-	op: OperationType,
-	by: OperationBy,
+impl ExpressionParser {
+	fn peek(&self) -> Option<&Token> {
+		self.tokens.get(self.position).map(|(_, token)| token)
+	}
+
+	fn offset(&self) -> usize {
+		self.tokens
+			.get(self.position)
+			.map_or(self.end_offset, |(offset, _)| *offset)
+	}
+
+	fn parse_binary(
+		&mut self,
+		operators: &[Operator],
+		parse_operand: fn(&mut Self) -> Result<Expression, String>,
+	) -> Result<Expression, String> {
+		let mut expression = parse_operand(self)?;
+		while let Some(Token::Operator(operator)) = self.peek() {
+			let operator = *operator;
+			if !operators.contains(&operator) {
+				break;
+			}
+			self.position += 1;
+			let rhs = parse_operand(self)?;
+			expression = Expression::Binary(Box::new(expression), operator, Box::new(rhs));
+		}
+		Ok(expression)
+	}
+
+	fn parse_sum(&mut self) -> Result<Expression, String> {
+		self.parse_binary(&[Operator::Add, Operator::Subtract], Self::parse_product)
+	}
+
+	fn parse_product(&mut self) -> Result<Expression, String> {
+		self.parse_binary(
+			&[Operator::Multiply, Operator::Divide, Operator::Remainder],
+			Self::parse_term,
+		)
+	}
+
+	fn parse_term(&mut self) -> Result<Expression, String> {
+		let offset = self.offset();
+		let expression = match self.tokens.get(self.position) {
+			Some((_, Token::Old)) => Expression::Old,
+			Some((_, Token::Number(number))) => Expression::Number(*number),
+			Some((_, Token::OpenParen)) => {
+				self.position += 1;
+				let expression = self.parse_sum()?;
+				if self.peek() != Some(&Token::CloseParen) {
+					return Err(format!("Expected ) at offset {}", self.offset()));
+				}
+				expression
+			}
+			Some((_, token)) => return Err(format!("Unexpected {:?} at offset {}", token, offset)),
+			None => return Err(format!("Unexpected end of expression at offset {}", offset)),
+		};
+		self.position += 1;
+		Ok(expression)
+	}
 }
 
-impl Operation {
-	fn apply(&self, operand: u32) -> u32 {
-		let by = match self.by {
-			OperationBy::OldValue => operand,
-			OperationBy::Number(num) => num,
+impl FromStr for Expression {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let mut parser = ExpressionParser {
+			tokens: tokenize(s)?,
+			position: 0,
+			end_offset: s.len(),
 		};
-		self.op.apply(by, operand)
+		let expression = parser.parse_sum()?;
+		if parser.position < parser.tokens.len() {
+			return Err(format!("Unexpected trailing input at offset {}", parser.offset()));
+		}
+		Ok(expression)
+	}
+}
+
+impl Expression {
+	fn evaluate(&self, old: u64) -> Result<u64, String> {
+		match self {
+			Self::Old => Ok(old),
+			Self::Number(number) => Ok(*number),
+			Self::Binary(lhs, operator, rhs) => {
+				let lhs = lhs.evaluate(old)?;
+				let rhs = rhs.evaluate(old)?;
+				let result = match operator {
+					Operator::Add => lhs.checked_add(rhs),
+					Operator::Subtract => lhs.checked_sub(rhs),
+					Operator::Multiply => lhs.checked_mul(rhs),
+					Operator::Divide => lhs.checked_div(rhs),
+					Operator::Remainder => lhs.checked_rem(rhs),
+				};
+				result.ok_or_else(|| format!("Worry level can't be calculated: {} {:?} {}", lhs, operator, rhs))
+			}
+		}
 	}
 }
 
 struct Monkey {
-	items: Vec<u32>,
-	worry_operation: Operation,
-	test_modulus: u32,
+	items: Vec<u64>,
+	worry_operation: Expression,
+	test_modulus: u64,
 	true_destination: usize,
 	false_destination: usize,
 	throws_made: usize,
@@ -53,12 +200,9 @@ fn main() -> Result<(), Box<dyn Error>> {
 		let input_string = fs::read_to_string("input.txt")?;
 		let mut input_lines = input_string.lines().filter(|s| !s.is_empty());
 		// Assume the monkeys are in order
-		let mut monkey_items: Vec<u32> = Vec::new();
-		let mut monkey_operation = Operation {
-			op: OperationType::Add,
-			by: OperationBy::Number(0),
-		};
-		let mut test_modulus: u32 = 1;
+		let mut monkey_items: Vec<u64> = Vec::new();
+		let mut monkey_operation = Expression::Old;
+		let mut test_modulus: u64 = 1;
 		let mut true_destination: usize = 0;
 		let mut false_destination: usize = 0;
 
@@ -70,7 +214,7 @@ fn main() -> Result<(), Box<dyn Error>> {
 			if line.starts_with("Monkey ") {
 				let new_monkey = Monkey {
 					items: monkey_items.clone(),
-					worry_operation: monkey_operation,
+					worry_operation: monkey_operation.clone(),
 					test_modulus,
 					true_destination,
 					false_destination,
@@ -80,24 +224,8 @@ fn main() -> Result<(), Box<dyn Error>> {
 				monkey_items.clear();
 			} else if let Some(items) = line.strip_prefix("  Starting items: ") {
 				monkey_items = items.split(", ").map(|item| item.parse().unwrap()).collect();
-			} else if let Some(operation) = line.strip_prefix("  Operation: new = old ") {
-				let mut parts = operation.split(' ');
-				let operator = parts.next().unwrap();
-				let amount = parts.next().unwrap();
-				assert!(parts.next().is_none(), "All data has been parsed");
-				let operator = match operator {
-					"+" => OperationType::Add,
-					"*" => OperationType::Multiply,
-					_ => panic!("Unhandled operation type: {}", operator),
-				};
-				let amount = match amount {
-					"old" => OperationBy::OldValue,
-					_ => OperationBy::Number(amount.parse()?),
-				};
-				monkey_operation = Operation {
-					op: operator,
-					by: amount,
-				};
+			} else if let Some(operation) = line.strip_prefix("  Operation: new = ") {
+				monkey_operation = operation.parse()?;
 			} else if let Some(modulus) = line.strip_prefix("  Test: divisible by ") {
 				test_modulus = modulus.parse().unwrap();
 			} else if let Some(dest) = line.strip_prefix("    If true: throw to monkey ") {
@@ -120,13 +248,13 @@ fn main() -> Result<(), Box<dyn Error>> {
 		monkeys
 	};
 
-	let mut append_to_true: Vec<u32> = Vec::new();
-	let mut append_to_false: Vec<u32> = Vec::new();
+	let mut append_to_true: Vec<u64> = Vec::new();
+	let mut append_to_false: Vec<u64> = Vec::new();
 
 	for _ in 0..20 {
 		for monkey_index in 0..monkeys.len() {
 			for item in monkeys[monkey_index].items.iter() {
-				let new_worry = monkeys[monkey_index].worry_operation.apply(*item) / 3;
+				let new_worry = monkeys[monkey_index].worry_operation.evaluate(*item)? / 3;
 				if new_worry % monkeys[monkey_index].test_modulus == 0 {
 					append_to_true.push(new_worry);
 				} else {
@@ -1,216 +1,1017 @@
-use std::collections::BinaryHeap;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::env;
 use std::error::Error;
 use std::fs;
+use std::hash::Hash;
+use std::str::FromStr;
 
-const DEFAULT_WORRY_MOD: u64 = 1007;
+trait WorryLevel: Clone {
+	fn constant(&self, number: u64) -> Self;
+	fn add(&self, rhs: &Self) -> Self;
+	fn sub(&self, rhs: &Self) -> Result<Self, String>;
+	fn mul(&self, rhs: &Self) -> Self;
+	fn div(&self, rhs: &Self) -> Result<Self, String>;
+	fn rem(&self, rhs: &Self) -> Result<Self, String>;
+	fn is_divisible_by(&self, divisor: u64) -> bool;
+}
+
+// A worry level kept modulo a multiple of every test modulus, which preserves the results of all the monkeys' tests
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+struct Modular<T> {
+	value: T,
+	modulus: T,
+}
+
+impl Modular<u64> {
+	fn new(value: u64, modulus: u64) -> Self {
+		Self {
+			value: value % modulus,
+			modulus,
+		}
+	}
+}
+
+impl WorryLevel for Modular<u64> {
+	fn constant(&self, number: u64) -> Self {
+		Self::new(number, self.modulus)
+	}
+
+	fn add(&self, rhs: &Self) -> Self {
+		debug_assert_eq!(self.modulus, rhs.modulus);
+		let sum = (self.value as u128 + rhs.value as u128) % self.modulus as u128;
+		Self::new(sum as u64, self.modulus)
+	}
+
+	fn sub(&self, _: &Self) -> Result<Self, String> {
+		Err("Modular worry levels can't be subtracted".into())
+	}
+
+	fn mul(&self, rhs: &Self) -> Self {
+		// Both values are below the modulus, so the full product always fits in a u128
+		debug_assert_eq!(self.modulus, rhs.modulus);
+		let product = (self.value as u128 * rhs.value as u128) % self.modulus as u128;
+		Self::new(product as u64, self.modulus)
+	}
 
-#[derive(Clone, Copy)]
-struct WorryAmount {
-	basis: u64,
-	mult: u64,
+	fn div(&self, _: &Self) -> Result<Self, String> {
+		Err("Modular worry levels can't be divided".into())
+	}
+
+	fn rem(&self, _: &Self) -> Result<Self, String> {
+		Err("Modular worry levels can't take remainders".into())
+	}
+
+	fn is_divisible_by(&self, divisor: u64) -> bool {
+		debug_assert!(self.modulus.is_multiple_of(divisor));
+		self.value.is_multiple_of(divisor)
+	}
+}
+
+// An exact worry level of any size, for operations that modular worry levels can't keep track of
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+struct BigUint {
+	// Least significant first, without any leading zero limbs
+	limbs: Vec<u32>,
 }
 
-impl WorryAmount {
-	fn new(num: u64, worry_mod: u64) -> Self {
-		let basis = num % worry_mod;
-		let mult = num / worry_mod;
-		Self { basis, mult }
+impl BigUint {
+	fn from_u64(value: u64) -> Self {
+		let mut number = Self {
+			limbs: vec![value as u32, (value >> 32) as u32],
+		};
+		number.trim();
+		number
+	}
+
+	fn trim(&mut self) {
+		while self.limbs.last() == Some(&0) {
+			self.limbs.pop();
+		}
+	}
+
+	fn rem_u64(&self, divisor: u64) -> u64 {
+		let mut remainder: u128 = 0;
+		for limb in self.limbs.iter().rev() {
+			remainder = ((remainder << 32) | *limb as u128) % divisor as u128;
+		}
+		remainder as u64
+	}
+
+	fn checked_sub(&self, rhs: &Self) -> Option<Self> {
+		if *self < *rhs {
+			return None;
+		}
+		let mut limbs: Vec<u32> = Vec::with_capacity(self.limbs.len());
+		let mut borrow: i64 = 0;
+		for (index, limb) in self.limbs.iter().enumerate() {
+			let mut difference = *limb as i64 - *rhs.limbs.get(index).unwrap_or(&0) as i64 - borrow;
+			borrow = 0;
+			if difference < 0 {
+				difference += 1 << 32;
+				borrow = 1;
+			}
+			limbs.push(difference as u32);
+		}
+		let mut difference = Self { limbs };
+		difference.trim();
+		Some(difference)
 	}
 
-	fn is_divisible(&self, modulus: u64, worry_mod: u64) -> bool {
-		((worry_mod % modulus) * self.mult + self.basis) % modulus == 0
+	// Long division a bit at a time, which is slow but only needed for unusual rule sets
+	fn div_rem(&self, divisor: &Self) -> Option<(Self, Self)> {
+		if divisor.limbs.is_empty() {
+			return None;
+		}
+		let mut quotient = Self {
+			limbs: vec![0; self.limbs.len()],
+		};
+		let mut remainder = Self { limbs: Vec::new() };
+		for bit in (0..self.limbs.len() * 32).rev() {
+			let mut carry = (self.limbs[bit / 32] >> (bit % 32)) & 1;
+			for limb in remainder.limbs.iter_mut() {
+				let shifted_out = *limb >> 31;
+				*limb = (*limb << 1) | carry;
+				carry = shifted_out;
+			}
+			if carry != 0 {
+				remainder.limbs.push(carry);
+			}
+			if remainder >= *divisor {
+				remainder = remainder.checked_sub(divisor)?;
+				quotient.limbs[bit / 32] |= 1 << (bit % 32);
+			}
+		}
+		quotient.trim();
+		Some((quotient, remainder))
 	}
+}
 
-	fn rebalance(&self, old_worry_mod: u64, new_worry_mod: u64) -> Self {
-		// This implementation assumes it happens before the number is big enough that it no longer fits.
-		let num = self.mult * old_worry_mod + self.basis;
-		Self::new(num, new_worry_mod)
+impl Ord for BigUint {
+	fn cmp(&self, other: &Self) -> Ordering {
+		self.limbs
+			.len()
+			.cmp(&other.limbs.len())
+			.then_with(|| self.limbs.iter().rev().cmp(other.limbs.iter().rev()))
 	}
 }
 
-#[derive(Clone, Copy, Eq, PartialEq)]
-enum OperationType {
+impl PartialOrd for BigUint {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+impl WorryLevel for BigUint {
+	fn constant(&self, number: u64) -> Self {
+		Self::from_u64(number)
+	}
+
+	fn add(&self, rhs: &Self) -> Self {
+		let mut limbs: Vec<u32> = Vec::with_capacity(self.limbs.len().max(rhs.limbs.len()) + 1);
+		let mut carry: u64 = 0;
+		for index in 0..self.limbs.len().max(rhs.limbs.len()) {
+			let sum = *self.limbs.get(index).unwrap_or(&0) as u64 + *rhs.limbs.get(index).unwrap_or(&0) as u64 + carry;
+			limbs.push(sum as u32);
+			carry = sum >> 32;
+		}
+		limbs.push(carry as u32);
+		let mut sum = Self { limbs };
+		sum.trim();
+		sum
+	}
+
+	fn sub(&self, rhs: &Self) -> Result<Self, String> {
+		self.checked_sub(rhs)
+			.ok_or_else(|| String::from("Worry level went below zero"))
+	}
+
+	fn mul(&self, rhs: &Self) -> Self {
+		let mut limbs: Vec<u32> = vec![0; self.limbs.len() + rhs.limbs.len()];
+		for (lhs_index, lhs_limb) in self.limbs.iter().enumerate() {
+			let mut carry: u64 = 0;
+			for (rhs_index, rhs_limb) in rhs.limbs.iter().enumerate() {
+				let product = *lhs_limb as u64 * *rhs_limb as u64 + limbs[lhs_index + rhs_index] as u64 + carry;
+				limbs[lhs_index + rhs_index] = product as u32;
+				carry = product >> 32;
+			}
+			limbs[lhs_index + rhs.limbs.len()] = carry as u32;
+		}
+		let mut product = Self { limbs };
+		product.trim();
+		product
+	}
+
+	fn div(&self, rhs: &Self) -> Result<Self, String> {
+		self.div_rem(rhs)
+			.map(|(quotient, _)| quotient)
+			.ok_or_else(|| String::from("Worry level divided by zero"))
+	}
+
+	fn rem(&self, rhs: &Self) -> Result<Self, String> {
+		self.div_rem(rhs)
+			.map(|(_, remainder)| remainder)
+			.ok_or_else(|| String::from("Remainder of a worry level divided by zero"))
+	}
+
+	fn is_divisible_by(&self, divisor: u64) -> bool {
+		self.rem_u64(divisor) == 0
+	}
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Operator {
 	Add,
+	Subtract,
 	Multiply,
+	Divide,
+	Remainder,
 }
 
-impl OperationType {
-	fn apply(&self, lhs: &WorryAmount, rhs: &WorryAmount, worry_mod: u64) -> WorryAmount {
-		match self {
-			Self::Add => {
-				let mut basis = lhs.basis + rhs.basis;
-				let mut mult = lhs.mult + rhs.mult;
-				mult += basis / worry_mod;
-				basis %= worry_mod;
-				mult %= worry_mod;
-				WorryAmount { basis, mult }
+impl Operator {
+	fn from_char(c: char) -> Option<Self> {
+		match c {
+			'+' => Some(Self::Add),
+			'-' => Some(Self::Subtract),
+			'*' => Some(Self::Multiply),
+			'/' => Some(Self::Divide),
+			'%' => Some(Self::Remainder),
+			_ => None,
+		}
+	}
+}
+
+#[derive(Clone, Debug)]
+enum Expression {
+	Old,
+	Number(u64),
+	Binary(Box<Expression>, Operator, Box<Expression>),
+}
+
+#[derive(Debug, Eq, PartialEq)]
+enum Token {
+	Old,
+	Number(u64),
+	Operator(Operator),
+	OpenParen,
+	CloseParen,
+}
+
+fn tokenize(s: &str) -> Result<Vec<(usize, Token)>, String> {
+	let mut tokens: Vec<(usize, Token)> = Vec::new();
+	let mut chars = s.char_indices().peekable();
+	while let Some((offset, c)) = chars.next() {
+		let token = if c.is_whitespace() {
+			continue;
+		} else if c == '(' {
+			Token::OpenParen
+		} else if c == ')' {
+			Token::CloseParen
+		} else if let Some(operator) = Operator::from_char(c) {
+			Token::Operator(operator)
+		} else if c.is_ascii_digit() {
+			let mut number = String::from(c);
+			while let Some((_, digit)) = chars.next_if(|(_, c)| c.is_ascii_digit()) {
+				number.push(digit);
 			}
-			Self::Multiply => {
-				let mut basis = lhs.basis * rhs.basis;
-				let mut mult = (lhs.mult * rhs.basis) + (lhs.basis * rhs.mult);
-				mult += basis / worry_mod;
-				basis %= worry_mod;
-				mult %= worry_mod;
-				WorryAmount { basis, mult }
+			Token::Number(
+				number
+					.parse()
+					.map_err(|_| format!("Number too large at offset {}: {}", offset, number))?,
+			)
+		} else if c.is_ascii_alphabetic() {
+			let mut word = String::from(c);
+			while let Some((_, letter)) = chars.next_if(|(_, c)| c.is_ascii_alphanumeric()) {
+				word.push(letter);
 			}
-		}
+			if word != "old" {
+				return Err(format!("Unknown name at offset {}: {}", offset, word));
+			}
+			Token::Old
+		} else {
+			return Err(format!("Unexpected character at offset {}: {}", offset, c));
+		};
+		tokens.push((offset, token));
 	}
+	Ok(tokens)
 }
 
-#[derive(Clone, Copy)]
-enum OperationBy {
-	OldValue,
-	Number(WorryAmount),
+// Recursive descent over the usual precedence levels: + and - bind loosest, then *, / and %, all left associative
+struct ExpressionParser {
+	tokens: Vec<(usize, Token)>,
+	position: usize,
+	end_offset: usize,
 }
 
-#[derive(Clone, Copy)]
-struct Operation {
-	op: OperationType,
-	by: OperationBy,
+impl ExpressionParser {
+	fn peek(&self) -> Option<&Token> {
+		self.tokens.get(self.position).map(|(_, token)| token)
+	}
+
+	fn offset(&self) -> usize {
+		self.tokens
+			.get(self.position)
+			.map_or(self.end_offset, |(offset, _)| *offset)
+	}
+
+	fn parse_binary(
+		&mut self,
+		operators: &[Operator],
+		parse_operand: fn(&mut Self) -> Result<Expression, String>,
+	) -> Result<Expression, String> {
+		let mut expression = parse_operand(self)?;
+		while let Some(Token::Operator(operator)) = self.peek() {
+			let operator = *operator;
+			if !operators.contains(&operator) {
+				break;
+			}
+			self.position += 1;
+			let rhs = parse_operand(self)?;
+			expression = Expression::Binary(Box::new(expression), operator, Box::new(rhs));
+		}
+		Ok(expression)
+	}
+
+	fn parse_sum(&mut self) -> Result<Expression, String> {
+		self.parse_binary(&[Operator::Add, Operator::Subtract], Self::parse_product)
+	}
+
+	fn parse_product(&mut self) -> Result<Expression, String> {
+		self.parse_binary(
+			&[Operator::Multiply, Operator::Divide, Operator::Remainder],
+			Self::parse_term,
+		)
+	}
+
+	fn parse_term(&mut self) -> Result<Expression, String> {
+		let offset = self.offset();
+		let expression = match self.tokens.get(self.position) {
+			Some((_, Token::Old)) => Expression::Old,
+			Some((_, Token::Number(number))) => Expression::Number(*number),
+			Some((_, Token::OpenParen)) => {
+				self.position += 1;
+				let expression = self.parse_sum()?;
+				if self.peek() != Some(&Token::CloseParen) {
+					return Err(format!("Expected ) at offset {}", self.offset()));
+				}
+				expression
+			}
+			Some((_, token)) => return Err(format!("Unexpected {:?} at offset {}", token, offset)),
+			None => return Err(format!("Unexpected end of expression at offset {}", offset)),
+		};
+		self.position += 1;
+		Ok(expression)
+	}
 }
 
-impl Operation {
-	fn apply(&self, operand: &WorryAmount, worry_mod: u64) -> WorryAmount {
-		let by = match &self.by {
-			OperationBy::OldValue => operand,
-			OperationBy::Number(num) => num,
+impl FromStr for Expression {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let mut parser = ExpressionParser {
+			tokens: tokenize(s)?,
+			position: 0,
+			end_offset: s.len(),
 		};
-		self.op.apply(by, operand, worry_mod)
+		let expression = parser.parse_sum()?;
+		if parser.position < parser.tokens.len() {
+			return Err(format!("Unexpected trailing input at offset {}", parser.offset()));
+		}
+		Ok(expression)
 	}
 }
 
-struct Monkey {
-	items: Vec<WorryAmount>,
-	worry_operation: Operation,
+impl Expression {
+	// Modular worry levels are only kept modulo the test moduli, which division and remainders don't respect. Subtraction
+	// does, but a real worry level can go negative where a modular one would silently wrap, so it's ruled out too.
+	fn supports_modular_arithmetic(&self) -> bool {
+		match self {
+			Self::Old | Self::Number(_) => true,
+			Self::Binary(lhs, operator, rhs) => {
+				matches!(operator, Operator::Add | Operator::Multiply)
+					&& lhs.supports_modular_arithmetic()
+					&& rhs.supports_modular_arithmetic()
+			}
+		}
+	}
+
+	fn evaluate<W: WorryLevel>(&self, old: &W) -> Result<W, String> {
+		match self {
+			Self::Old => Ok(old.clone()),
+			Self::Number(number) => Ok(old.constant(*number)),
+			Self::Binary(lhs, operator, rhs) => {
+				let lhs = lhs.evaluate(old)?;
+				let rhs = rhs.evaluate(old)?;
+				match operator {
+					Operator::Add => Ok(lhs.add(&rhs)),
+					Operator::Subtract => lhs.sub(&rhs),
+					Operator::Multiply => Ok(lhs.mul(&rhs)),
+					Operator::Divide => lhs.div(&rhs),
+					Operator::Remainder => lhs.rem(&rhs),
+				}
+			}
+		}
+	}
+}
+
+#[derive(Clone)]
+struct Item<W> {
+	id: usize,
+	worry: W,
+}
+
+struct Monkey<W> {
+	items: Vec<Item<W>>,
+	worry_operation: Expression,
 	test_modulus: u64,
 	true_destination: usize,
 	false_destination: usize,
 	throws_made: usize,
 }
 
+impl<W> Monkey<W> {
+	fn destination<V: WorryLevel>(&self, worry: &V) -> usize {
+		if worry.is_divisible_by(self.test_modulus) {
+			self.true_destination
+		} else {
+			self.false_destination
+		}
+	}
+}
+
+impl Monkey<u64> {
+	fn with_worry_levels<W>(&self, worry_level: impl Fn(u64) -> W) -> Monkey<W> {
+		Monkey {
+			items: self
+				.items
+				.iter()
+				.map(|item| Item {
+					id: item.id,
+					worry: worry_level(item.worry),
+				})
+				.collect(),
+			worry_operation: self.worry_operation.clone(),
+			test_modulus: self.test_modulus,
+			true_destination: self.true_destination,
+			false_destination: self.false_destination,
+			throws_made: self.throws_made,
+		}
+	}
+}
+
+// Plays a round, calling on_throw with the item ID and destination monkey for every throw
+fn play_round<W: WorryLevel>(monkeys: &mut [Monkey<W>], mut on_throw: impl FnMut(usize, usize)) -> Result<(), String> {
+	let mut append_to_true: Vec<Item<W>> = Vec::new();
+	let mut append_to_false: Vec<Item<W>> = Vec::new();
+
+	for monkey_index in 0..monkeys.len() {
+		for item in monkeys[monkey_index].items.iter() {
+			let new_worry = monkeys[monkey_index].worry_operation.evaluate(&item.worry)?;
+			let new_item = Item {
+				id: item.id,
+				worry: new_worry,
+			};
+			if new_item.worry.is_divisible_by(monkeys[monkey_index].test_modulus) {
+				append_to_true.push(new_item);
+			} else {
+				append_to_false.push(new_item);
+			};
+		}
+		monkeys[monkey_index].throws_made += monkeys[monkey_index].items.len();
+		monkeys[monkey_index].items.clear();
+
+		let true_destination = monkeys[monkey_index].true_destination;
+		let false_destination = monkeys[monkey_index].false_destination;
+
+		for item in append_to_true.drain(..) {
+			on_throw(item.id, true_destination);
+			monkeys[true_destination].items.push(item);
+		}
+		for item in append_to_false.drain(..) {
+			on_throw(item.id, false_destination);
+			monkeys[false_destination].items.push(item);
+		}
+	}
+
+	Ok(())
+}
+
+struct KeepAwaySimulation<W> {
+	monkeys: Vec<Monkey<W>>,
+	starting_items: Vec<(usize, Item<W>)>,
+	inspections_per_round: Vec<Vec<usize>>,
+	item_paths: Vec<Vec<usize>>,
+}
+
+impl<W: WorryLevel + Eq + Hash> KeepAwaySimulation<W> {
+	fn new(monkeys: &[Monkey<u64>], worry_level: impl Fn(u64) -> W) -> Self {
+		let monkeys: Vec<Monkey<W>> = monkeys
+			.iter()
+			.map(|monkey| monkey.with_worry_levels(&worry_level))
+			.collect();
+
+		let mut starting_items: Vec<(usize, Item<W>)> = Vec::new();
+		for (monkey_index, monkey) in monkeys.iter().enumerate() {
+			starting_items.extend(monkey.items.iter().map(|item| (monkey_index, item.clone())));
+		}
+		starting_items.sort_by_key(|(_, item)| item.id);
+
+		let item_paths = starting_items
+			.iter()
+			.map(|(monkey_index, _)| vec![*monkey_index])
+			.collect();
+
+		Self {
+			monkeys,
+			starting_items,
+			inspections_per_round: Vec::new(),
+			item_paths,
+		}
+	}
+
+	fn rounds_played(&self) -> usize {
+		self.inspections_per_round.len()
+	}
+
+	fn play_round(&mut self) -> Result<(), String> {
+		let throws_before: Vec<usize> = self.monkeys.iter().map(|monkey| monkey.throws_made).collect();
+		let item_paths = &mut self.item_paths;
+		play_round(&mut self.monkeys, |item_id, destination| {
+			item_paths[item_id].push(destination)
+		})?;
+		self.inspections_per_round.push(
+			self.monkeys
+				.iter()
+				.zip(throws_before.iter())
+				.map(|(monkey, throws_before)| monkey.throws_made - throws_before)
+				.collect(),
+		);
+		Ok(())
+	}
+
+	fn total_inspections(&self) -> Vec<u64> {
+		self.monkeys.iter().map(|monkey| monkey.throws_made as u64).collect()
+	}
+
+	// Follows a single item through one round, starting with the monkey holding it. It keeps being inspected as long as
+	// it's thrown to monkeys that haven't had their turn yet.
+	fn play_item_round(
+		&self,
+		mut monkey_index: usize,
+		mut worry: W,
+		inspections: &mut [u64],
+	) -> Result<(usize, W), String> {
+		loop {
+			let monkey = &self.monkeys[monkey_index];
+			worry = monkey.worry_operation.evaluate(&worry)?;
+			inspections[monkey_index] += 1;
+			let destination = monkey.destination(&worry);
+			if destination <= monkey_index {
+				return Ok((destination, worry));
+			}
+			monkey_index = destination;
+		}
+	}
+
+	// Each item moves independently of the others, and where it goes next depends only on which monkey holds it and its
+	// worry level, so with modular worry levels every item's routing eventually repeats. Finding each item's cycle lets
+	// the inspection counts for any number of rounds be worked out without playing them all. Exact worry levels may
+	// never repeat, in which case every round gets played.
+	fn fast_forward_inspections(&self, rounds: u64) -> Result<Vec<u64>, String> {
+		let monkey_count = self.monkeys.len();
+		let mut totals = vec![0u64; monkey_count];
+
+		for (starting_monkey, item) in self.starting_items.iter() {
+			let mut seen_states: HashMap<(usize, W), usize> = HashMap::new();
+			// Inspections of this item by each monkey over the first n rounds
+			let mut cumulative_inspections: Vec<Vec<u64>> = vec![vec![0; monkey_count]];
+			let mut state = (*starting_monkey, item.worry.clone());
+			loop {
+				let round = cumulative_inspections.len() - 1;
+				if round as u64 == rounds {
+					for (total, inspections) in totals.iter_mut().zip(cumulative_inspections[round].iter()) {
+						*total += inspections;
+					}
+					break;
+				}
+
+				if let Some(cycle_start) = seen_states.insert(state.clone(), round) {
+					let cycle_length = (round - cycle_start) as u64;
+					let remaining_rounds = rounds - round as u64;
+					let full_cycles = remaining_rounds / cycle_length;
+					let leftover_rounds = (remaining_rounds % cycle_length) as usize;
+					let before_cycle = &cumulative_inspections[cycle_start];
+					let after_cycle = &cumulative_inspections[round];
+					let after_leftover = &cumulative_inspections[cycle_start + leftover_rounds];
+					for monkey_index in 0..monkey_count {
+						let per_cycle = after_cycle[monkey_index] - before_cycle[monkey_index];
+						let leftover = after_leftover[monkey_index] - before_cycle[monkey_index];
+						totals[monkey_index] += after_cycle[monkey_index] + full_cycles * per_cycle + leftover;
+					}
+					break;
+				}
+
+				let mut inspections = cumulative_inspections[round].clone();
+				state = self.play_item_round(state.0, state.1, &mut inspections)?;
+				cumulative_inspections.push(inspections);
+			}
+		}
+
+		Ok(totals)
+	}
+}
+
+fn monkey_business(inspections: &[u64]) -> u128 {
+	let mut monkey_throws: BinaryHeap<u64> = inspections.iter().copied().collect();
+	let most_throws = monkey_throws.pop().unwrap_or(0);
+	let second_most_throws = monkey_throws.pop().unwrap_or(0);
+	most_throws as u128 * second_most_throws as u128
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+	if b == 0 {
+		a
+	} else {
+		gcd(b, a % b)
+	}
+}
+
+fn parse_monkeys(input: &str) -> Result<Vec<Monkey<u64>>, Box<dyn Error>> {
+	let mut input_lines = input.lines().filter(|s| !s.is_empty());
+
+	let mut monkey_items: Vec<Item<u64>> = Vec::new();
+	let mut next_item_id: usize = 0;
+	let mut monkey_operation = Expression::Old;
+	let mut test_modulus: u64 = 1;
+	let mut true_destination: usize = 0;
+	let mut false_destination: usize = 0;
+
+	input_lines.next(); // Eat the first monkey's header
+
+	let mut monkeys: Vec<Monkey<u64>> = Vec::new();
+
+	for line in input_lines {
+		if line.starts_with("Monkey ") {
+			// Assume the monkeys are in order in the input file
+			let new_monkey = Monkey {
+				items: monkey_items.clone(),
+				worry_operation: monkey_operation.clone(),
+				test_modulus,
+				true_destination,
+				false_destination,
+				throws_made: 0,
+			};
+			monkeys.push(new_monkey);
+			monkey_items.clear();
+		} else if let Some(items) = line.strip_prefix("  Starting items: ") {
+			for item in items.split(", ") {
+				monkey_items.push(Item {
+					id: next_item_id,
+					worry: item.parse().unwrap(),
+				});
+				next_item_id += 1;
+			}
+		} else if let Some(operation) = line.strip_prefix("  Operation: new = ") {
+			monkey_operation = operation.parse()?;
+		} else if let Some(modulus) = line.strip_prefix("  Test: divisible by ") {
+			test_modulus = modulus.parse().unwrap();
+			if test_modulus == 0 {
+				return Err("Monkeys can't test for divisibility by 0".into());
+			}
+		} else if let Some(dest) = line.strip_prefix("    If true: throw to monkey ") {
+			true_destination = dest.parse().unwrap();
+		} else if let Some(dest) = line.strip_prefix("    If false: throw to monkey ") {
+			false_destination = dest.parse().unwrap();
+		} else {
+			eprintln!("Unparsed line: [{}]", line);
+		}
+	}
+	monkeys.push(Monkey {
+		items: monkey_items,
+		worry_operation: monkey_operation,
+		test_modulus,
+		true_destination,
+		false_destination,
+		throws_made: 0,
+	});
+
+	Ok(monkeys)
+}
+
+fn worry_modulus(monkeys: &[Monkey<u64>]) -> Result<u64, String> {
+	monkeys
+		.iter()
+		.try_fold(1u64, |lcm, monkey| {
+			(lcm / gcd(lcm, monkey.test_modulus)).checked_mul(monkey.test_modulus)
+		})
+		.ok_or_else(|| String::from("The least common multiple of the test moduli doesn't fit in a u64"))
+}
+
+// Counts each monkey's inspections over the given rounds, printing the statistics asked for along the way
+fn count_inspections<W: WorryLevel + Eq + Hash>(
+	mut simulation: KeepAwaySimulation<W>,
+	rounds: u64,
+	show_round_stats: bool,
+	show_item_paths: bool,
+) -> Result<Vec<u64>, Box<dyn Error>> {
+	let inspections = simulation.fast_forward_inspections(rounds)?;
+
+	// The statistics need every round played out, which also double checks the fast-forwarded counts
+	if show_round_stats || show_item_paths {
+		while (simulation.rounds_played() as u64) < rounds {
+			simulation.play_round()?;
+		}
+		if simulation.total_inspections() != inspections {
+			return Err("Fast-forwarded inspection counts don't match the simulated ones".into());
+		}
+
+		if show_round_stats {
+			for (round_index, round_inspections) in simulation.inspections_per_round.iter().enumerate() {
+				let counts: Vec<String> = round_inspections.iter().map(|count| count.to_string()).collect();
+				println!("Round {}: {}", round_index + 1, counts.join(" "));
+			}
+		}
+		if show_item_paths {
+			for (item_id, path) in simulation.item_paths.iter().enumerate() {
+				let path: Vec<String> = path.iter().map(|monkey_index| monkey_index.to_string()).collect();
+				println!("Item {}: {}", item_id, path.join(" -> "));
+			}
+		}
+	}
+
+	Ok(inspections)
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
-	let mut monkeys = {
+	let mut rounds: u64 = 10000;
+	let mut show_round_stats = false;
+	let mut show_item_paths = false;
+	let mut args = env::args().skip(1);
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--rounds" => rounds = args.next().ok_or("--rounds requires a round count")?.parse()?,
+			"--round-stats" => show_round_stats = true,
+			"--item-paths" => show_item_paths = true,
+			_ => return Err(format!("unknown argument: {}", arg).into()),
+		}
+	}
+
+	let parsed_monkeys = {
 		let input_string = fs::read_to_string("input.txt")?;
-		let mut input_lines = input_string.lines().filter(|s| !s.is_empty());
+		parse_monkeys(&input_string)?
+	};
 
-		let mut monkey_items: Vec<WorryAmount> = Vec::new();
-		let mut monkey_operation = Operation {
-			op: OperationType::Add,
-			by: OperationBy::Number(WorryAmount { basis: 0, mult: 0 }),
-		};
-		let mut test_modulus: u64 = 1;
-		let mut true_destination: usize = 0;
-		let mut false_destination: usize = 0;
-
-		input_lines.next(); // Eat the first monkey's header
-
-		let mut monkeys: Vec<Monkey> = Vec::new();
-
-		for line in input_lines {
-			if line.starts_with("Monkey ") {
-				// Assume the monkeys are in order in the input file
-				let new_monkey = Monkey {
-					items: monkey_items.clone(),
-					worry_operation: monkey_operation,
-					test_modulus,
-					true_destination,
-					false_destination,
-					throws_made: 0,
+	// Modular worry levels stay small however many rounds are played, but only some operations work on them. Exact ones
+	// work with any operation, but rules that keep multiplying them make every round slower than the last.
+	let inspections = if parsed_monkeys
+		.iter()
+		.all(|monkey| monkey.worry_operation.supports_modular_arithmetic())
+	{
+		let worry_mod = worry_modulus(&parsed_monkeys)?;
+		let simulation = KeepAwaySimulation::new(&parsed_monkeys, |item| Modular::new(item, worry_mod));
+		count_inspections(simulation, rounds, show_round_stats, show_item_paths)?
+	} else {
+		let simulation = KeepAwaySimulation::new(&parsed_monkeys, BigUint::from_u64);
+		count_inspections(simulation, rounds, show_round_stats, show_item_paths)?
+	};
+
+	println!("{}", monkey_business(&inspections));
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const SAMPLE: &str = "Monkey 0:
+  Starting items: 79, 98
+  Operation: new = old * 19
+  Test: divisible by 23
+    If true: throw to monkey 2
+    If false: throw to monkey 3
+
+Monkey 1:
+  Starting items: 54, 65, 75, 74
+  Operation: new = old + 6
+  Test: divisible by 19
+    If true: throw to monkey 2
+    If false: throw to monkey 0
+
+Monkey 2:
+  Starting items: 79, 60, 97
+  Operation: new = old * old
+  Test: divisible by 13
+    If true: throw to monkey 1
+    If false: throw to monkey 3
+
+Monkey 3:
+  Starting items: 74
+  Operation: new = old + 3
+  Test: divisible by 17
+    If true: throw to monkey 0
+    If false: throw to monkey 1
+";
+
+	// Just enough of an arbitrary-precision integer to check the modular simulation against the exact one
+	#[derive(Clone, Debug)]
+	struct Xorshift(u64);
+
+	impl Xorshift {
+		fn below(&mut self, limit: u64) -> u64 {
+			self.0 ^= self.0 << 13;
+			self.0 ^= self.0 >> 7;
+			self.0 ^= self.0 << 17;
+			self.0 % limit
+		}
+	}
+
+	// At most one monkey squares the worry level, so that exact worry levels stay small enough to multiply quickly
+	fn random_monkeys(rng: &mut Xorshift) -> Vec<Monkey<u64>> {
+		let monkey_count = 2 + rng.below(5) as usize;
+		let squaring_monkey = rng.below(monkey_count as u64 + 1) as usize;
+		let mut next_item_id = 0;
+		(0..monkey_count)
+			.map(|monkey_index| {
+				let operation = match rng.below(3) {
+					_ if monkey_index == squaring_monkey => String::from("old * old"),
+					0 => format!("old + {}", 1 + rng.below(10)),
+					1 => format!("old * {}", 2 + rng.below(20)),
+					_ => format!("(old + {}) * {}", rng.below(10), 1 + rng.below(5)),
 				};
-				monkeys.push(new_monkey);
-				monkey_items.clear();
-			} else if let Some(items) = line.strip_prefix("  Starting items: ") {
-				monkey_items = items
-					.split(", ")
-					.map(|item| {
-						let item: u64 = item.parse().unwrap();
-						WorryAmount::new(item, DEFAULT_WORRY_MOD)
+				let items = (0..rng.below(5))
+					.map(|_| {
+						next_item_id += 1;
+						Item {
+							id: next_item_id - 1,
+							worry: 1 + rng.below(100),
+						}
 					})
 					.collect();
-			} else if let Some(operation) = line.strip_prefix("  Operation: new = old ") {
-				let mut parts = operation.split(' ');
-				let operator = parts.next().unwrap();
-				let amount = parts.next().unwrap();
-				assert!(parts.next().is_none(), "All data has been parsed");
-				let operator = match operator {
-					"+" => OperationType::Add,
-					"*" => OperationType::Multiply,
-					_ => panic!("Unhandled operation type: {}", operator),
-				};
-				let amount = match amount {
-					"old" => OperationBy::OldValue,
-					_ => {
-						let amount: u64 = amount.parse()?;
-						OperationBy::Number(WorryAmount::new(amount, DEFAULT_WORRY_MOD))
+				let mut other_monkey = || {
+					let destination = rng.below(monkey_count as u64 - 1) as usize;
+					if destination >= monkey_index {
+						destination + 1
+					} else {
+						destination
 					}
 				};
-				monkey_operation = Operation {
-					op: operator,
-					by: amount,
-				};
-			} else if let Some(modulus) = line.strip_prefix("  Test: divisible by ") {
-				test_modulus = modulus.parse().unwrap();
-			} else if let Some(dest) = line.strip_prefix("    If true: throw to monkey ") {
-				true_destination = dest.parse().unwrap();
-			} else if let Some(dest) = line.strip_prefix("    If false: throw to monkey ") {
-				false_destination = dest.parse().unwrap();
-			} else {
-				eprintln!("Unparsed line: [{}]", line);
+				Monkey {
+					items,
+					worry_operation: operation.parse().unwrap(),
+					true_destination: other_monkey(),
+					false_destination: other_monkey(),
+					test_modulus: [2, 3, 5, 7, 11, 13, 17, 19, 23][rng.below(9) as usize],
+					throws_made: 0,
+				}
+			})
+			.collect()
+	}
+
+	// Plays the given number of rounds with both modular and exact worry levels and checks that they agree on every
+	// monkey's throw count and on the residue of every item it's holding
+	fn assert_modular_matches_exact(monkeys: &[Monkey<u64>], rounds: usize) {
+		let worry_mod = worry_modulus(monkeys).unwrap();
+		let mut modular_monkeys: Vec<Monkey<Modular<u64>>> = monkeys
+			.iter()
+			.map(|monkey| monkey.with_worry_levels(|item| Modular::new(item, worry_mod)))
+			.collect();
+		let mut exact_monkeys: Vec<Monkey<BigUint>> = monkeys
+			.iter()
+			.map(|monkey| monkey.with_worry_levels(BigUint::from_u64))
+			.collect();
+
+		for round in 1..=rounds {
+			play_round(&mut modular_monkeys, |_, _| ()).unwrap();
+			play_round(&mut exact_monkeys, |_, _| ()).unwrap();
+
+			for (monkey_index, (modular_monkey, exact_monkey)) in
+				modular_monkeys.iter().zip(exact_monkeys.iter()).enumerate()
+			{
+				let items_match =
+					modular_monkey.items.len() == exact_monkey.items.len()
+						&& modular_monkey.items.iter().zip(exact_monkey.items.iter()).all(
+							|(modular_item, exact_item)| {
+								modular_item.id == exact_item.id
+									&& modular_item.worry.value == exact_item.worry.rem_u64(worry_mod)
+							},
+						);
+				assert!(
+					modular_monkey.throws_made == exact_monkey.throws_made && items_match,
+					"Modular worry levels diverged from exact ones for monkey {} in round {}",
+					monkey_index,
+					round
+				);
 			}
 		}
-		monkeys.push(Monkey {
-			items: monkey_items,
-			worry_operation: monkey_operation,
-			test_modulus,
-			true_destination,
-			false_destination,
-			throws_made: 0,
-		});
+	}
 
-		monkeys
-	};
+	#[test]
+	fn sample_matches_exact_worry_levels() {
+		let monkeys = parse_monkeys(SAMPLE).unwrap();
+		assert_modular_matches_exact(&monkeys, 20);
+	}
 
-	let worry_mod: u64 = monkeys.iter().map(|monkey| monkey.test_modulus).product();
-	for monkey in monkeys.iter_mut() {
-		for item in monkey.items.iter_mut() {
-			*item = item.rebalance(DEFAULT_WORRY_MOD, worry_mod);
+	#[test]
+	fn generated_monkeys_match_exact_worry_levels() {
+		let mut rng = Xorshift(0x2545_f491_4f6c_dd1d);
+		for _ in 0..100 {
+			let monkeys = random_monkeys(&mut rng);
+			assert_modular_matches_exact(&monkeys, 10);
 		}
 	}
 
-	let mut append_to_true: Vec<WorryAmount> = Vec::new();
-	let mut append_to_false: Vec<WorryAmount> = Vec::new();
+	#[test]
+	fn sample_monkey_business() {
+		let monkeys = parse_monkeys(SAMPLE).unwrap();
+		let worry_mod = worry_modulus(&monkeys).unwrap();
+		let simulation = KeepAwaySimulation::new(&monkeys, |item| Modular::new(item, worry_mod));
+		assert_eq!(simulation.fast_forward_inspections(20).unwrap(), vec![99, 97, 8, 103]);
+		assert_eq!(
+			monkey_business(&simulation.fast_forward_inspections(10000).unwrap()),
+			2713310158
+		);
+	}
 
-	for _ in 0..10000 {
-		for monkey_index in 0..monkeys.len() {
-			for item in monkeys[monkey_index].items.iter() {
-				let new_worry = monkeys[monkey_index].worry_operation.apply(item, worry_mod);
-				if new_worry.is_divisible(monkeys[monkey_index].test_modulus, worry_mod) {
-					append_to_true.push(new_worry);
-				} else {
-					append_to_false.push(new_worry);
-				};
-			}
-			monkeys[monkey_index].throws_made += monkeys[monkey_index].items.len();
-			monkeys[monkey_index].items.clear();
+	// Plain integers as a reference for exact worry levels, as long as they don't overflow
+	impl WorryLevel for u64 {
+		fn constant(&self, number: u64) -> Self {
+			number
+		}
 
-			let true_destination = monkeys[monkey_index].true_destination;
-			let false_destination = monkeys[monkey_index].false_destination;
+		fn add(&self, rhs: &Self) -> Self {
+			self.checked_add(*rhs).unwrap()
+		}
 
-			for item in append_to_true.drain(..) {
-				monkeys[true_destination].items.push(item);
-			}
-			for item in append_to_false.drain(..) {
-				monkeys[false_destination].items.push(item);
-			}
+		fn sub(&self, rhs: &Self) -> Result<Self, String> {
+			self.checked_sub(*rhs).ok_or_else(|| String::from("negative"))
+		}
+
+		fn mul(&self, rhs: &Self) -> Self {
+			self.checked_mul(*rhs).unwrap()
+		}
+
+		fn div(&self, rhs: &Self) -> Result<Self, String> {
+			self.checked_div(*rhs).ok_or_else(|| String::from("division by zero"))
+		}
+
+		fn rem(&self, rhs: &Self) -> Result<Self, String> {
+			self.checked_rem(*rhs).ok_or_else(|| String::from("division by zero"))
+		}
+
+		fn is_divisible_by(&self, divisor: u64) -> bool {
+			self.is_multiple_of(divisor)
 		}
 	}
 
-	let mut monkey_throws: BinaryHeap<usize> = monkeys.iter().map(|monkey| monkey.throws_made).collect();
-	let most_throws = monkey_throws.pop().unwrap();
-	let second_most_throws = monkey_throws.pop().unwrap();
+	#[test]
+	fn subtraction_is_not_modular() {
+		let expression: Expression = "old - 100".parse().unwrap();
+		assert!(!expression.supports_modular_arithmetic());
+		assert!(expression.evaluate(&Modular::new(50, 7)).is_err());
+		assert_eq!(expression.evaluate(&BigUint::from_u64(500)), Ok(BigUint::from_u64(400)));
+		assert!(expression.evaluate(&BigUint::from_u64(50)).is_err());
+	}
 
-	let monkey_business = most_throws * second_most_throws;
-	println!("{}", monkey_business);
+	#[test]
+	fn big_integer_division() {
+		let numerator = BigUint::from_u64(u64::MAX)
+			.mul(&BigUint::from_u64(1_000_003))
+			.add(&BigUint::from_u64(12345));
+		let (quotient, remainder) = numerator.div_rem(&BigUint::from_u64(u64::MAX)).unwrap();
+		assert_eq!(quotient, BigUint::from_u64(1_000_003));
+		assert_eq!(remainder, BigUint::from_u64(12345));
+		assert_eq!(BigUint::from_u64(7).div_rem(&BigUint::from_u64(0)), None);
+		assert_eq!(
+			BigUint::from_u64(7).div_rem(&BigUint::from_u64(9)),
+			Some((BigUint::from_u64(0), BigUint::from_u64(7)))
+		);
+	}
 
-	Ok(())
+	// Rules using -, / and % fall back to exact worry levels, which have to agree with plain integers
+	#[test]
+	fn exact_worry_levels_for_other_operators() {
+		let input = SAMPLE
+			.replace("old * 19", "(old * 19 + 5) % 1009")
+			.replace("old * old", "old * old % 9973")
+			.replace("old + 3", "(old + 3) / 2 * 3 - old % 2");
+		let monkeys = parse_monkeys(&input).unwrap();
+		assert!(!monkeys
+			.iter()
+			.all(|monkey| monkey.worry_operation.supports_modular_arithmetic()));
+
+		let mut reference_monkeys: Vec<Monkey<u64>> = monkeys
+			.iter()
+			.map(|monkey| monkey.with_worry_levels(|item| item))
+			.collect();
+		let simulation = KeepAwaySimulation::new(&monkeys, BigUint::from_u64);
+		for _ in 0..20 {
+			play_round(&mut reference_monkeys, |_, _| ()).unwrap();
+		}
+		let reference_inspections: Vec<u64> = reference_monkeys
+			.iter()
+			.map(|monkey| monkey.throws_made as u64)
+			.collect();
+		assert_eq!(simulation.fast_forward_inspections(20).unwrap(), reference_inspections);
+	}
 }
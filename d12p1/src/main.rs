@@ -1,7 +1,9 @@
 use std::cmp::{Ordering, Reverse};
-use std::collections::{BinaryHeap, HashSet};
+use std::collections::{BinaryHeap, HashMap};
+use std::env;
 use std::error::Error;
 use std::fs;
+use std::str::FromStr;
 
 #[derive(Clone, Copy, Eq, Hash, Ord, PartialEq, PartialOrd)]
 struct Coordinate {
@@ -10,47 +12,202 @@ struct Coordinate {
 }
 
 impl Coordinate {
-	fn left(&self) -> Option<Self> {
-		if self.x > 0 {
-			Some(Self {
-				x: self.x - 1,
-				y: self.y,
-			})
+	fn offset(&self, x_offset: isize, y_offset: isize, height_map: &[Vec<usize>]) -> Option<Self> {
+		let x = self.x.checked_add_signed(x_offset)?;
+		let y = self.y.checked_add_signed(y_offset)?;
+		if y < height_map.len() && x < height_map[y].len() {
+			Some(Self { x, y })
 		} else {
 			None
 		}
 	}
+}
+
+#[derive(Clone, Copy)]
+struct MovementRules {
+	max_climb: usize,
+	max_descent: Option<usize>,
+	diagonal: bool,
+}
 
-	fn up(&self) -> Option<Self> {
-		if self.y > 0 {
-			Some(Self {
-				x: self.x,
-				y: self.y - 1,
-			})
+impl Default for MovementRules {
+	fn default() -> Self {
+		Self {
+			max_climb: 1,
+			max_descent: None,
+			diagonal: false,
+		}
+	}
+}
+
+impl MovementRules {
+	fn can_move(&self, from_height: usize, to_height: usize) -> bool {
+		if to_height > from_height {
+			to_height - from_height <= self.max_climb
 		} else {
-			None
+			self.max_descent
+				.is_none_or(|max_descent| from_height - to_height <= max_descent)
 		}
 	}
 
-	fn right(&self) -> Self {
-		Self {
-			x: self.x + 1,
-			y: self.y,
+	// Coordinates one step away, whether or not the height difference allows moving there
+	fn neighbours(&self, coord: Coordinate, height_map: &[Vec<usize>]) -> Vec<Coordinate> {
+		let mut offsets: Vec<(isize, isize)> = vec![(-1, 0), (0, -1), (1, 0), (0, 1)];
+		if self.diagonal {
+			offsets.extend([(-1, -1), (1, -1), (1, 1), (-1, 1)]);
 		}
+		offsets
+			.into_iter()
+			.filter_map(|(x_offset, y_offset)| coord.offset(x_offset, y_offset, height_map))
+			.collect()
 	}
+}
 
-	fn down(&self) -> Self {
-		Self {
-			x: self.x,
-			y: self.y + 1,
+#[derive(Clone, Copy)]
+enum ElevationEncoding {
+	// Each cell is this many lowercase letters, read as a base 26 number
+	Letters(usize),
+	// Whitespace- or comma-separated numbers
+	Numeric,
+}
+
+impl FromStr for ElevationEncoding {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		if s == "numeric" {
+			return Ok(Self::Numeric);
+		}
+		if s == "letters" {
+			return Ok(Self::Letters(1));
+		}
+		if let Some(width) = s.strip_prefix("letters:") {
+			let width: usize = width.parse().map_err(|_| format!("Invalid letter count: {}", width))?;
+			if width == 0 || 26usize.checked_pow(width as u32).is_none() {
+				return Err(format!("Unsupported letter count: {}", width));
+			}
+			return Ok(Self::Letters(width));
 		}
+		Err(format!("Unknown elevation encoding: {}", s))
 	}
 }
 
+enum Cell {
+	Start(usize),
+	End(usize),
+	Height(usize),
+}
+
+impl ElevationEncoding {
+	fn split_row<'a>(&self, row: &'a str) -> Result<Vec<&'a str>, String> {
+		match self {
+			Self::Letters(width) => {
+				if !row.is_ascii() || !row.len().is_multiple_of(*width) {
+					return Err(format!("Row isn't made of {}-letter cells: {}", width, row));
+				}
+				Ok((0..row.len())
+					.step_by(*width)
+					.map(|index| &row[index..index + width])
+					.collect())
+			}
+			Self::Numeric => Ok(row
+				.split(|c: char| c.is_whitespace() || c == ',')
+				.filter(|token| !token.is_empty())
+				.collect()),
+		}
+	}
+
+	fn parse_height(&self, token: &str) -> Option<usize> {
+		match self {
+			Self::Letters(_) => token.chars().try_fold(0usize, |height, c| {
+				c.is_ascii_lowercase()
+					.then(|| height * 26 + (c as usize - 'a' as usize))
+			}),
+			Self::Numeric => token.parse().ok(),
+		}
+	}
+
+	// As with the single letter map, where S is at elevation a and E at elevation z, a run of S or E letters is at
+	// the lowest or highest elevation. Numeric maps mark the start or end by prefixing its height with S or E.
+	fn parse_cell(&self, token: &str) -> Option<Cell> {
+		match self {
+			Self::Letters(width) => {
+				if token.chars().all(|c| c == 'S') {
+					Some(Cell::Start(0))
+				} else if token.chars().all(|c| c == 'E') {
+					Some(Cell::End(26usize.pow(*width as u32) - 1))
+				} else {
+					self.parse_height(token).map(Cell::Height)
+				}
+			}
+			Self::Numeric => {
+				if let Some(height) = token.strip_prefix('S') {
+					self.parse_height(height).map(Cell::Start)
+				} else if let Some(height) = token.strip_prefix('E') {
+					self.parse_height(height).map(Cell::End)
+				} else {
+					self.parse_height(token).map(Cell::Height)
+				}
+			}
+		}
+	}
+}
+
+struct HeightMap {
+	start: Option<Coordinate>,
+	end: Option<Coordinate>,
+	heights: Vec<Vec<usize>>,
+}
+
+fn parse_height_map(input: &str, encoding: ElevationEncoding) -> Result<HeightMap, String> {
+	let mut start: Option<Coordinate> = None;
+	let mut end: Option<Coordinate> = None;
+	let mut heights: Vec<Vec<usize>> = Vec::new();
+
+	for (y, line) in input.lines().filter(|line| !line.is_empty()).enumerate() {
+		let mut row: Vec<usize> = Vec::new();
+		for (x, token) in encoding.split_row(line)?.into_iter().enumerate() {
+			let coord = Coordinate { x, y };
+			let height = match encoding.parse_cell(token) {
+				Some(Cell::Start(height)) => {
+					if start.replace(coord).is_some() {
+						return Err(format!("Second start found at row {}, column {}", y, x));
+					}
+					height
+				}
+				Some(Cell::End(height)) => {
+					if end.replace(coord).is_some() {
+						return Err(format!("Second end found at row {}, column {}", y, x));
+					}
+					height
+				}
+				Some(Cell::Height(height)) => height,
+				None => return Err(format!("Unexpected elevation at row {}, column {}: {}", y, x, token)),
+			};
+			row.push(height);
+		}
+
+		if let Some(first_row) = heights.first() {
+			if row.len() != first_row.len() {
+				return Err(format!(
+					"Row {} is {} cells wide, but the map is {} cells wide",
+					y,
+					row.len(),
+					first_row.len()
+				));
+			}
+		}
+		heights.push(row);
+	}
+
+	Ok(HeightMap { start, end, heights })
+}
+
 #[derive(Clone, Eq, PartialEq)]
 struct PathProgress {
 	coord: Coordinate,
 	steps_taken: u32,
+	previous_coord: Option<Coordinate>,
 }
 
 impl Ord for PathProgress {
@@ -58,6 +215,7 @@ impl Ord for PathProgress {
 		self.steps_taken
 			.cmp(&other.steps_taken)
 			.then_with(|| self.coord.cmp(&other.coord))
+			.then_with(|| self.previous_coord.cmp(&other.previous_coord))
 	}
 }
 
@@ -67,100 +225,135 @@ impl PartialOrd for PathProgress {
 	}
 }
 
+fn reconstruct_route(
+	previous_coords: &HashMap<Coordinate, Option<Coordinate>>,
+	end_coord: Coordinate,
+) -> Vec<Coordinate> {
+	let mut route = vec![end_coord];
+	while let Some(Some(previous_coord)) = previous_coords.get(route.last().unwrap()) {
+		route.push(*previous_coord);
+	}
+	route.reverse();
+	route
+}
+
+// Draws the route over the map the way the puzzle does: each step is an arrow pointing at the next one
+fn render_route(height_map: &[Vec<usize>], route: &[Coordinate]) -> String {
+	let mut grid: Vec<Vec<char>> = height_map.iter().map(|row| vec!['.'; row.len()]).collect();
+	for step in route.windows(2) {
+		let (from, to) = (step[0], step[1]);
+		grid[from.y][from.x] = match (to.x.cmp(&from.x), to.y.cmp(&from.y)) {
+			(Ordering::Greater, Ordering::Equal) => '>',
+			(Ordering::Less, Ordering::Equal) => '<',
+			(Ordering::Equal, Ordering::Greater) => 'v',
+			(Ordering::Equal, _) => '^',
+			// Diagonal steps, when they're allowed
+			(Ordering::Greater, Ordering::Less) | (Ordering::Less, Ordering::Greater) => '/',
+			_ => '\\',
+		};
+	}
+	if let Some(end_coord) = route.last() {
+		grid[end_coord.y][end_coord.x] = 'E';
+	}
+
+	let mut output = String::new();
+	for row in grid.iter() {
+		output.extend(row.iter());
+		output.push('\n');
+	}
+	output
+}
+
+fn route_json(height_map: &[Vec<usize>], route: &[Coordinate]) -> String {
+	let steps: Vec<String> = route
+		.iter()
+		.map(|coord| {
+			format!(
+				"{{\"x\":{},\"y\":{},\"height\":{}}}",
+				coord.x, coord.y, height_map[coord.y][coord.x]
+			)
+		})
+		.collect();
+	format!(
+		"{{\"steps\":{},\"route\":[{}]}}\n",
+		route.len().saturating_sub(1),
+		steps.join(",")
+	)
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
+	let mut render = false;
+	let mut json_path: Option<String> = None;
+	let mut encoding = ElevationEncoding::Letters(1);
+	let mut movement_rules = MovementRules::default();
+	let mut args = env::args().skip(1);
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--render" => render = true,
+			"--json" => json_path = Some(args.next().ok_or("--json requires an output path")?),
+			"--encoding" => encoding = args.next().ok_or("--encoding requires an encoding")?.parse()?,
+			"--max-climb" => movement_rules.max_climb = args.next().ok_or("--max-climb requires a height")?.parse()?,
+			"--max-descent" => {
+				movement_rules.max_descent = Some(args.next().ok_or("--max-descent requires a height")?.parse()?)
+			}
+			"--diagonal" => movement_rules.diagonal = true,
+			_ => return Err(format!("unknown argument: {}", arg).into()),
+		}
+	}
+
 	let (start_coord, end_coord, height_map) = {
 		let input_string = fs::read_to_string("input.txt")?;
-		let mut start_coord: Option<Coordinate> = None;
-		let mut end_coord: Option<Coordinate> = None;
-		let height_map: Vec<Vec<usize>> = input_string
-			.lines()
-			.enumerate()
-			.map(|(line_index, line)| {
-				line.chars()
-					.enumerate()
-					.map(|(c_index, c)| match c {
-						'S' => {
-							start_coord = Some(Coordinate {
-								x: c_index,
-								y: line_index,
-							});
-							0
-						}
-						'E' => {
-							end_coord = Some(Coordinate {
-								x: c_index,
-								y: line_index,
-							});
-							25
-						}
-						_ => (c as usize) - 97,
-					})
-					.collect()
-			})
-			.collect();
-		(start_coord.unwrap(), end_coord.unwrap(), height_map)
+		let height_map = parse_height_map(&input_string, encoding)?;
+		let start_coord = height_map.start.ok_or("The map has no start")?;
+		let end_coord = height_map.end.ok_or("The map has no end")?;
+		(start_coord, end_coord, height_map.heights)
 	};
 
 	let start_path = PathProgress {
 		coord: start_coord,
 		steps_taken: 0,
+		previous_coord: None,
 	};
 	let mut paths = BinaryHeap::new();
 	paths.push(Reverse(start_path));
-	let mut visited_coords: HashSet<Coordinate> = HashSet::new();
+	// Every visited coordinate, along with the one it was first reached from
+	let mut visited_coords: HashMap<Coordinate, Option<Coordinate>> = HashMap::new();
 
-	let mut steps_taken = 0;
+	let mut route: Option<Vec<Coordinate>> = None;
 	while let Some(Reverse(path)) = paths.pop() {
+		if visited_coords.contains_key(&path.coord) {
+			continue;
+		}
+		visited_coords.insert(path.coord, path.previous_coord);
 		if path.coord == end_coord {
-			steps_taken = path.steps_taken;
+			route = Some(reconstruct_route(&visited_coords, end_coord));
 			break;
 		}
-		if visited_coords.contains(&path.coord) {
-			continue;
-		}
 
 		let steps_taken = path.steps_taken + 1;
-		visited_coords.insert(path.coord);
+		let previous_coord = Some(path.coord);
 		let current_height = height_map[path.coord.y][path.coord.x];
 
-		if let Some(left_coord) = path.coord.left() {
-			if height_map[left_coord.y][left_coord.x] <= current_height + 1 && !visited_coords.contains(&left_coord) {
+		for next_coord in movement_rules.neighbours(path.coord, &height_map) {
+			if movement_rules.can_move(current_height, height_map[next_coord.y][next_coord.x])
+				&& !visited_coords.contains_key(&next_coord)
+			{
 				paths.push(Reverse(PathProgress {
-					coord: left_coord,
+					coord: next_coord,
 					steps_taken,
+					previous_coord,
 				}));
 			}
 		}
+	}
 
-		if let Some(up_coord) = path.coord.up() {
-			if height_map[up_coord.y][up_coord.x] <= current_height + 1 && !visited_coords.contains(&up_coord) {
-				paths.push(Reverse(PathProgress {
-					coord: up_coord,
-					steps_taken,
-				}));
-			}
+	let steps_taken = route.as_ref().map_or(0, |route| route.len() - 1);
+	if let Some(route) = route.as_ref() {
+		if render {
+			print!("{}", render_route(&height_map, route));
 		}
-
-		let right_coord = path.coord.right();
-		if right_coord.x < height_map[right_coord.y].len()
-			&& height_map[right_coord.y][right_coord.x] <= current_height + 1
-			&& !visited_coords.contains(&right_coord)
-		{
-			paths.push(Reverse(PathProgress {
-				coord: right_coord,
-				steps_taken,
-			}));
-		}
-
-		let down_coord = path.coord.down();
-		if down_coord.y < height_map.len()
-			&& height_map[down_coord.y][down_coord.x] <= current_height + 1
-			&& !visited_coords.contains(&down_coord)
-		{
-			paths.push(Reverse(PathProgress {
-				coord: down_coord,
-				steps_taken,
-			}));
+		if let Some(path) = json_path {
+			fs::write(path, route_json(&height_map, route))?;
 		}
 	}
 
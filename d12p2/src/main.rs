@@ -1,7 +1,8 @@
-use std::cmp::{Ordering, Reverse};
-use std::collections::{BinaryHeap, HashSet};
+use std::collections::VecDeque;
+use std::env;
 use std::error::Error;
 use std::fs;
+use std::str::FromStr;
 
 #[derive(Clone, Copy, Eq, Hash, Ord, PartialEq, PartialOrd)]
 struct Coordinate {
@@ -10,159 +11,306 @@ struct Coordinate {
 }
 
 impl Coordinate {
-	fn left(&self) -> Option<Self> {
-		if self.x > 0 {
-			Some(Self {
-				x: self.x - 1,
-				y: self.y,
-			})
+	fn offset(&self, x_offset: isize, y_offset: isize, height_map: &[Vec<usize>]) -> Option<Self> {
+		let x = self.x.checked_add_signed(x_offset)?;
+		let y = self.y.checked_add_signed(y_offset)?;
+		if y < height_map.len() && x < height_map[y].len() {
+			Some(Self { x, y })
 		} else {
 			None
 		}
 	}
+}
 
-	fn up(&self) -> Option<Self> {
-		if self.y > 0 {
-			Some(Self {
-				x: self.x,
-				y: self.y - 1,
-			})
-		} else {
-			None
+#[derive(Clone, Copy)]
+struct MovementRules {
+	max_climb: usize,
+	max_descent: Option<usize>,
+	diagonal: bool,
+}
+
+impl Default for MovementRules {
+	fn default() -> Self {
+		Self {
+			max_climb: 1,
+			max_descent: None,
+			diagonal: false,
 		}
 	}
+}
 
-	fn right(&self) -> Self {
-		Self {
-			x: self.x + 1,
-			y: self.y,
+impl MovementRules {
+	fn can_move(&self, from_height: usize, to_height: usize) -> bool {
+		if to_height > from_height {
+			to_height - from_height <= self.max_climb
+		} else {
+			self.max_descent
+				.is_none_or(|max_descent| from_height - to_height <= max_descent)
 		}
 	}
 
-	fn down(&self) -> Self {
-		Self {
-			x: self.x,
-			y: self.y + 1,
+	// Coordinates one step away, whether or not the height difference allows moving there
+	fn neighbours(&self, coord: Coordinate, height_map: &[Vec<usize>]) -> Vec<Coordinate> {
+		let mut offsets: Vec<(isize, isize)> = vec![(-1, 0), (0, -1), (1, 0), (0, 1)];
+		if self.diagonal {
+			offsets.extend([(-1, -1), (1, -1), (1, 1), (-1, 1)]);
 		}
+		offsets
+			.into_iter()
+			.filter_map(|(x_offset, y_offset)| coord.offset(x_offset, y_offset, height_map))
+			.collect()
 	}
 }
 
-#[derive(Clone, Eq, PartialEq)]
-struct PathProgress {
-	coord: Coordinate,
-	steps_taken: u32,
+#[derive(Clone, Copy)]
+enum ElevationEncoding {
+	// Each cell is this many lowercase letters, read as a base 26 number
+	Letters(usize),
+	// Whitespace- or comma-separated numbers
+	Numeric,
 }
 
-impl Ord for PathProgress {
-	fn cmp(&self, other: &Self) -> Ordering {
-		self.steps_taken
-			.cmp(&other.steps_taken)
-			.then_with(|| self.coord.cmp(&other.coord))
+impl FromStr for ElevationEncoding {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		if s == "numeric" {
+			return Ok(Self::Numeric);
+		}
+		if s == "letters" {
+			return Ok(Self::Letters(1));
+		}
+		if let Some(width) = s.strip_prefix("letters:") {
+			let width: usize = width.parse().map_err(|_| format!("Invalid letter count: {}", width))?;
+			if width == 0 || 26usize.checked_pow(width as u32).is_none() {
+				return Err(format!("Unsupported letter count: {}", width));
+			}
+			return Ok(Self::Letters(width));
+		}
+		Err(format!("Unknown elevation encoding: {}", s))
 	}
 }
 
-impl PartialOrd for PathProgress {
-	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-		Some(self.cmp(other))
-	}
+enum Cell {
+	Start(usize),
+	End(usize),
+	Height(usize),
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-	let (end_coord, height_map) = {
-		let input_string = fs::read_to_string("input.txt")?;
-		let mut end_coord: Option<Coordinate> = None;
-		let height_map: Vec<Vec<usize>> = input_string
-			.lines()
-			.enumerate()
-			.map(|(line_index, line)| {
-				line.chars()
-					.enumerate()
-					.map(|(c_index, c)| match c {
-						'S' => 0,
-						'E' => {
-							end_coord = Some(Coordinate {
-								x: c_index,
-								y: line_index,
-							});
-							25
-						}
-						_ => (c as usize) - 97,
-					})
-					.collect()
-			})
-			.collect();
-		(end_coord.unwrap(), height_map)
-	};
+impl ElevationEncoding {
+	fn split_row<'a>(&self, row: &'a str) -> Result<Vec<&'a str>, String> {
+		match self {
+			Self::Letters(width) => {
+				if !row.is_ascii() || !row.len().is_multiple_of(*width) {
+					return Err(format!("Row isn't made of {}-letter cells: {}", width, row));
+				}
+				Ok((0..row.len())
+					.step_by(*width)
+					.map(|index| &row[index..index + width])
+					.collect())
+			}
+			Self::Numeric => Ok(row
+				.split(|c: char| c.is_whitespace() || c == ',')
+				.filter(|token| !token.is_empty())
+				.collect()),
+		}
+	}
+
+	fn parse_height(&self, token: &str) -> Option<usize> {
+		match self {
+			Self::Letters(_) => token.chars().try_fold(0usize, |height, c| {
+				c.is_ascii_lowercase()
+					.then(|| height * 26 + (c as usize - 'a' as usize))
+			}),
+			Self::Numeric => token.parse().ok(),
+		}
+	}
 
-	let mut paths = BinaryHeap::new();
-	for (y, row) in height_map.iter().enumerate() {
-		for (x, height) in row.iter().enumerate() {
-			if *height == 0 {
-				paths.push(Reverse(PathProgress {
-					coord: Coordinate { x, y },
-					steps_taken: 0,
-				}));
+	// As with the single letter map, where S is at elevation a and E at elevation z, a run of S or E letters is at
+	// the lowest or highest elevation. Numeric maps mark the start or end by prefixing its height with S or E.
+	fn parse_cell(&self, token: &str) -> Option<Cell> {
+		match self {
+			Self::Letters(width) => {
+				if token.chars().all(|c| c == 'S') {
+					Some(Cell::Start(0))
+				} else if token.chars().all(|c| c == 'E') {
+					Some(Cell::End(26usize.pow(*width as u32) - 1))
+				} else {
+					self.parse_height(token).map(Cell::Height)
+				}
+			}
+			Self::Numeric => {
+				if let Some(height) = token.strip_prefix('S') {
+					self.parse_height(height).map(Cell::Start)
+				} else if let Some(height) = token.strip_prefix('E') {
+					self.parse_height(height).map(Cell::End)
+				} else {
+					self.parse_height(token).map(Cell::Height)
+				}
 			}
 		}
 	}
+}
 
-	let mut visited_coords: HashSet<Coordinate> = HashSet::new();
+struct HeightMap {
+	start: Option<Coordinate>,
+	end: Option<Coordinate>,
+	heights: Vec<Vec<usize>>,
+}
+
+fn parse_height_map(input: &str, encoding: ElevationEncoding) -> Result<HeightMap, String> {
+	let mut start: Option<Coordinate> = None;
+	let mut end: Option<Coordinate> = None;
+	let mut heights: Vec<Vec<usize>> = Vec::new();
 
-	let mut steps_taken = 0;
-	while let Some(Reverse(path)) = paths.pop() {
-		if path.coord == end_coord {
-			steps_taken = path.steps_taken;
-			break;
+	for (y, line) in input.lines().filter(|line| !line.is_empty()).enumerate() {
+		let mut row: Vec<usize> = Vec::new();
+		for (x, token) in encoding.split_row(line)?.into_iter().enumerate() {
+			let coord = Coordinate { x, y };
+			let height = match encoding.parse_cell(token) {
+				Some(Cell::Start(height)) => {
+					if start.replace(coord).is_some() {
+						return Err(format!("Second start found at row {}, column {}", y, x));
+					}
+					height
+				}
+				Some(Cell::End(height)) => {
+					if end.replace(coord).is_some() {
+						return Err(format!("Second end found at row {}, column {}", y, x));
+					}
+					height
+				}
+				Some(Cell::Height(height)) => height,
+				None => return Err(format!("Unexpected elevation at row {}, column {}: {}", y, x, token)),
+			};
+			row.push(height);
 		}
-		if visited_coords.contains(&path.coord) {
-			continue;
+
+		if let Some(first_row) = heights.first() {
+			if row.len() != first_row.len() {
+				return Err(format!(
+					"Row {} is {} cells wide, but the map is {} cells wide",
+					y,
+					row.len(),
+					first_row.len()
+				));
+			}
 		}
+		heights.push(row);
+	}
 
-		let steps_taken = path.steps_taken + 1;
-		visited_coords.insert(path.coord);
-		let current_height = height_map[path.coord.y][path.coord.x];
+	Ok(HeightMap { start, end, heights })
+}
 
-		if let Some(left_coord) = path.coord.left() {
-			if height_map[left_coord.y][left_coord.x] <= current_height + 1 && !visited_coords.contains(&left_coord) {
-				paths.push(Reverse(PathProgress {
-					coord: left_coord,
-					steps_taken,
-				}));
+// The number of steps from every coordinate to the end, found by searching backwards from the end
+struct DistanceField {
+	distances: Vec<Vec<Option<u32>>>,
+}
+
+impl DistanceField {
+	fn from_end(height_map: &[Vec<usize>], end_coord: Coordinate, movement_rules: &MovementRules) -> Self {
+		let mut distances: Vec<Vec<Option<u32>>> = height_map.iter().map(|row| vec![None; row.len()]).collect();
+		distances[end_coord.y][end_coord.x] = Some(0);
+		let mut queue: VecDeque<Coordinate> = VecDeque::from([end_coord]);
+
+		while let Some(coord) = queue.pop_front() {
+			let distance = distances[coord.y][coord.x].unwrap() + 1;
+			let height = height_map[coord.y][coord.x];
+			for neighbour in movement_rules.neighbours(coord, height_map) {
+				// Walking backwards, so the move is from the neighbour to this coordinate
+				if movement_rules.can_move(height_map[neighbour.y][neighbour.x], height)
+					&& distances[neighbour.y][neighbour.x].is_none()
+				{
+					distances[neighbour.y][neighbour.x] = Some(distance);
+					queue.push_back(neighbour);
+				}
 			}
 		}
 
-		if let Some(up_coord) = path.coord.up() {
-			if height_map[up_coord.y][up_coord.x] <= current_height + 1 && !visited_coords.contains(&up_coord) {
-				paths.push(Reverse(PathProgress {
-					coord: up_coord,
-					steps_taken,
-				}));
+		Self { distances }
+	}
+
+	fn distance_from_end(&self, coord: Coordinate) -> Option<u32> {
+		self.distances.get(coord.y)?.get(coord.x).copied().flatten()
+	}
+
+	fn nearest_with_height(&self, height_map: &[Vec<usize>], height: usize) -> Option<(Coordinate, u32)> {
+		let mut nearest: Option<(Coordinate, u32)> = None;
+		for (y, row) in height_map.iter().enumerate() {
+			for (x, coord_height) in row.iter().enumerate() {
+				if *coord_height != height {
+					continue;
+				}
+				let coord = Coordinate { x, y };
+				if let Some(distance) = self.distance_from_end(coord) {
+					if nearest.is_none_or(|(_, nearest_distance)| distance < nearest_distance) {
+						nearest = Some((coord, distance));
+					}
+				}
 			}
 		}
+		nearest
+	}
+}
+
+fn parse_coordinate(s: &str) -> Result<Coordinate, Box<dyn Error>> {
+	let (x, y) = s.split_once(',').ok_or("Coordinates must be given as x,y")?;
+	Ok(Coordinate {
+		x: x.trim().parse()?,
+		y: y.trim().parse()?,
+	})
+}
 
-		let right_coord = path.coord.right();
-		if right_coord.x < height_map[right_coord.y].len()
-			&& height_map[right_coord.y][right_coord.x] <= current_height + 1
-			&& !visited_coords.contains(&right_coord)
-		{
-			paths.push(Reverse(PathProgress {
-				coord: right_coord,
-				steps_taken,
-			}));
+fn main() -> Result<(), Box<dyn Error>> {
+	let mut from_coords: Vec<Coordinate> = Vec::new();
+	let mut elevations: Vec<String> = Vec::new();
+	let mut encoding = ElevationEncoding::Letters(1);
+	let mut movement_rules = MovementRules::default();
+	let mut args = env::args().skip(1);
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--from" => from_coords.push(parse_coordinate(&args.next().ok_or("--from requires a coordinate")?)?),
+			"--elevation" => elevations.push(args.next().ok_or("--elevation requires an elevation")?),
+			"--encoding" => encoding = args.next().ok_or("--encoding requires an encoding")?.parse()?,
+			"--max-climb" => movement_rules.max_climb = args.next().ok_or("--max-climb requires a height")?.parse()?,
+			"--max-descent" => {
+				movement_rules.max_descent = Some(args.next().ok_or("--max-descent requires a height")?.parse()?)
+			}
+			"--diagonal" => movement_rules.diagonal = true,
+			_ => return Err(format!("unknown argument: {}", arg).into()),
 		}
+	}
 
-		let down_coord = path.coord.down();
-		if down_coord.y < height_map.len()
-			&& height_map[down_coord.y][down_coord.x] <= current_height + 1
-			&& !visited_coords.contains(&down_coord)
-		{
-			paths.push(Reverse(PathProgress {
-				coord: down_coord,
-				steps_taken,
-			}));
+	let (end_coord, height_map, trail_height) = {
+		let input_string = fs::read_to_string("input.txt")?;
+		let height_map = parse_height_map(&input_string, encoding)?;
+		let end_coord = height_map.end.ok_or("The map has no end")?;
+		// The trail can start at any cell with the same height as the marked start, or the lowest height without one
+		let trail_height = height_map.start.map_or(0, |start| height_map.heights[start.y][start.x]);
+		(end_coord, height_map.heights, trail_height)
+	};
+
+	let distance_field = DistanceField::from_end(&height_map, end_coord, &movement_rules);
+
+	for coord in from_coords.iter() {
+		match distance_field.distance_from_end(*coord) {
+			Some(distance) => println!("{},{}: {}", coord.x, coord.y, distance),
+			None => println!("{},{}: unreachable", coord.x, coord.y),
+		}
+	}
+	for elevation_name in elevations.iter() {
+		let elevation = encoding
+			.parse_height(elevation_name)
+			.ok_or_else(|| format!("Invalid elevation: {}", elevation_name))?;
+		match distance_field.nearest_with_height(&height_map, elevation) {
+			Some((coord, distance)) => println!("{}: {},{} ({})", elevation_name, coord.x, coord.y, distance),
+			None => println!("{}: unreachable", elevation_name),
 		}
 	}
 
+	let (_, steps_taken) = distance_field
+		.nearest_with_height(&height_map, trail_height)
+		.ok_or("No cell at the trail's starting height can reach the end")?;
 	println!("{}", steps_taken);
 
 	Ok(())
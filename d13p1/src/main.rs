@@ -1,141 +1,384 @@
 use std::cmp::Ordering;
+use std::env;
 use std::error::Error;
+use std::fmt;
 use std::fs;
+use std::slice;
+use std::str::FromStr;
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 enum PacketDatum {
 	List(Vec<PacketDatum>),
 	Integer(i32),
 }
 
-fn packet_data_in_order(left: &PacketDatum, right: &PacketDatum) -> Option<bool> {
-	match (left, right) {
-		(PacketDatum::Integer(first), PacketDatum::Integer(second)) => match first.cmp(second) {
-			Ordering::Less => Some(true),
-			Ordering::Greater => Some(false),
-			Ordering::Equal => None,
-		},
-		(PacketDatum::List(first), PacketDatum::List(second)) => {
-			for index in 0..(first.len().max(second.len())) {
-				if index >= first.len() {
-					return Some(true);
-				}
-				if index >= second.len() {
-					return Some(false);
+// Packets are equal when neither is in order before the other, so [[1]], [1] and 1 are all equal
+impl Ord for PacketDatum {
+	fn cmp(&self, other: &Self) -> Ordering {
+		match (self, other) {
+			(Self::Integer(first), Self::Integer(second)) => first.cmp(second),
+			// Lists compare lexicographically, with a shorter list in order before a longer one it starts
+			(Self::List(first), Self::List(second)) => first.cmp(second),
+			(Self::Integer(_), Self::List(second)) => slice::from_ref(self).cmp(second.as_slice()),
+			(Self::List(first), Self::Integer(_)) => first.as_slice().cmp(slice::from_ref(other)),
+		}
+	}
+}
+
+impl PartialOrd for PacketDatum {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+impl PartialEq for PacketDatum {
+	fn eq(&self, other: &Self) -> bool {
+		self.cmp(other) == Ordering::Equal
+	}
+}
+
+impl Eq for PacketDatum {}
+
+impl fmt::Display for PacketDatum {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::Integer(value) => write!(f, "{}", value),
+			Self::List(list) => {
+				write!(f, "[")?;
+				for (index, datum) in list.iter().enumerate() {
+					if index > 0 {
+						write!(f, ",")?;
+					}
+					write!(f, "{}", datum)?;
 				}
-				if let Some(val) = packet_data_in_order(&first[index], &second[index]) {
-					return Some(val);
+				write!(f, "]")
+			}
+		}
+	}
+}
+
+// Recursive descent over the packet grammar, where a datum is an integer or a bracketed, comma separated list of data
+struct PacketParser<'a> {
+	input: &'a str,
+	offset: usize,
+}
+
+impl PacketParser<'_> {
+	fn peek(&self) -> Option<char> {
+		self.input[self.offset..].chars().next()
+	}
+
+	fn skip_whitespace(&mut self) {
+		while let Some(c) = self.peek().filter(|c| c.is_whitespace()) {
+			self.offset += c.len_utf8();
+		}
+	}
+
+	fn unexpected(&self, expected: &str) -> String {
+		match self.peek() {
+			Some(c) => format!("Expected {} at offset {}, found {}", expected, self.offset, c),
+			None => format!("Expected {} at offset {}, found end of packet", expected, self.offset),
+		}
+	}
+
+	fn parse_datum(&mut self) -> Result<PacketDatum, String> {
+		self.skip_whitespace();
+		match self.peek() {
+			Some('[') => self.parse_list(),
+			Some(c) if c == '-' || c.is_ascii_digit() => self.parse_integer(),
+			_ => Err(self.unexpected("[ or an integer")),
+		}
+	}
+
+	fn parse_list(&mut self) -> Result<PacketDatum, String> {
+		self.offset += 1; // Eat the [
+		let mut list: Vec<PacketDatum> = Vec::new();
+		self.skip_whitespace();
+		if self.peek() == Some(']') {
+			self.offset += 1;
+			return Ok(PacketDatum::List(list));
+		}
+
+		loop {
+			list.push(self.parse_datum()?);
+			self.skip_whitespace();
+			match self.peek() {
+				Some(',') => self.offset += 1,
+				Some(']') => {
+					self.offset += 1;
+					return Ok(PacketDatum::List(list));
 				}
+				_ => return Err(self.unexpected(", or ]")),
 			}
-			None
 		}
-		(PacketDatum::Integer(first), PacketDatum::List(_)) => {
-			packet_data_in_order(&PacketDatum::List(vec![PacketDatum::Integer(*first)]), right)
+	}
+
+	fn parse_integer(&mut self) -> Result<PacketDatum, String> {
+		let start = self.offset;
+		if self.peek() == Some('-') {
+			self.offset += 1;
 		}
-		(PacketDatum::List(_), PacketDatum::Integer(second)) => {
-			packet_data_in_order(left, &PacketDatum::List(vec![PacketDatum::Integer(*second)]))
+		let digits_start = self.offset;
+		while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+			self.offset += 1;
 		}
+		if self.offset == digits_start {
+			return Err(self.unexpected("a digit"));
+		}
+
+		let number = &self.input[start..self.offset];
+		number
+			.parse()
+			.map(PacketDatum::Integer)
+			.map_err(|_| format!("Integer out of range at offset {}: {}", start, number))
 	}
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-	let packets: Vec<(Vec<PacketDatum>, Vec<PacketDatum>)> = {
-		let input_string = fs::read_to_string("input.txt")?;
-		let mut packets = Vec::new();
-
-		let mut first_packet_list_parse: Vec<Vec<PacketDatum>> = vec![Vec::new()];
-		let mut second_packet_list_parse: Vec<Vec<PacketDatum>> = vec![Vec::new()];
-		let mut parsing_second = false;
-		for packet_str in input_string.lines().filter(|s| !s.is_empty()) {
-			let Some(packet_str) = packet_str.strip_prefix('[') else {
-				panic!("Invalid packet format");
-			};
-			let Some(packet_str) = packet_str.strip_suffix(']') else {
-				panic!("Invalid packet format");
-			};
-
-			for entry in packet_str.split(',') {
-				let mut datum = entry;
-				while let Some(datum_stripped) = datum.strip_prefix('[') {
-					if parsing_second {
-						second_packet_list_parse.push(Vec::new());
-					} else {
-						first_packet_list_parse.push(Vec::new());
-					}
-					datum = datum_stripped;
-				}
+impl FromStr for PacketDatum {
+	type Err = String;
 
-				let mut resolve_levels: u32 = 0;
-				while let Some(datum_stripped) = datum.strip_suffix(']') {
-					resolve_levels += 1;
-					datum = datum_stripped;
-				}
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let mut parser = PacketParser { input: s, offset: 0 };
+		let packet = parser.parse_datum()?;
+		parser.skip_whitespace();
+		if parser.offset < s.len() {
+			return Err(format!("Unexpected trailing input at offset {}", parser.offset));
+		}
+		Ok(packet)
+	}
+}
 
-				if !datum.is_empty() {
-					if parsing_second {
-						second_packet_list_parse
-							.last_mut()
-							.unwrap()
-							.push(PacketDatum::Integer(datum.parse()?));
-					} else {
-						first_packet_list_parse
-							.last_mut()
-							.unwrap()
-							.push(PacketDatum::Integer(datum.parse()?));
-					}
-				}
+#[derive(Clone, Copy, Debug)]
+enum Side {
+	Left,
+	Right,
+}
 
-				for _ in 0..resolve_levels {
-					if parsing_second {
-						let resolve_list = second_packet_list_parse.pop().unwrap();
-						second_packet_list_parse
-							.last_mut()
-							.unwrap()
-							.push(PacketDatum::List(resolve_list));
-					} else {
-						let resolve_list = first_packet_list_parse.pop().unwrap();
-						first_packet_list_parse
-							.last_mut()
-							.unwrap()
-							.push(PacketDatum::List(resolve_list));
-					}
+impl fmt::Display for Side {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::Left => write!(f, "left"),
+			Self::Right => write!(f, "right"),
+		}
+	}
+}
+
+// Indices into nested lists, from the outermost packet list inwards
+struct PacketPath(Vec<usize>);
+
+impl fmt::Display for PacketPath {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		if self.0.is_empty() {
+			return write!(f, "packet");
+		}
+		for index in self.0.iter() {
+			write!(f, "[{}]", index)?;
+		}
+		Ok(())
+	}
+}
+
+// An integer compared against a list, and so wrapped in a list of its own
+struct Promotion {
+	side: Side,
+	path: PacketPath,
+	value: i32,
+}
+
+// Why two packets compare the way they do: the data the decision was made on, and the puzzle's walkthrough of it
+struct Comparison {
+	ordering: Ordering,
+	decided_at: Option<(PacketPath, PacketPath)>,
+	promotions: Vec<Promotion>,
+	trace: Vec<String>,
+}
+
+impl Comparison {
+	fn new(left: &PacketDatum, right: &PacketDatum) -> Self {
+		let mut comparison = Self {
+			ordering: Ordering::Equal,
+			decided_at: None,
+			promotions: Vec::new(),
+			trace: Vec::new(),
+		};
+		comparison.ordering = comparison.compare(left, right, &mut Vec::new(), &mut Vec::new(), 0);
+		comparison
+	}
+
+	fn note(&mut self, depth: usize, line: String) {
+		self.trace.push(format!("{}- {}", "  ".repeat(depth), line));
+	}
+
+	fn decide(&mut self, ordering: Ordering, left_path: &[usize], right_path: &[usize]) -> Ordering {
+		if ordering != Ordering::Equal {
+			self.decided_at = Some((PacketPath(left_path.to_vec()), PacketPath(right_path.to_vec())));
+		}
+		ordering
+	}
+
+	fn compare(
+		&mut self,
+		left: &PacketDatum,
+		right: &PacketDatum,
+		left_path: &mut Vec<usize>,
+		right_path: &mut Vec<usize>,
+		depth: usize,
+	) -> Ordering {
+		self.note(depth, format!("Compare {} vs {}", left, right));
+		match (left, right) {
+			(PacketDatum::Integer(first), PacketDatum::Integer(second)) => {
+				let ordering = first.cmp(second);
+				match ordering {
+					Ordering::Less => self.note(
+						depth + 1,
+						"Left side is smaller, so inputs are in the right order".into(),
+					),
+					Ordering::Greater => self.note(
+						depth + 1,
+						"Right side is smaller, so inputs are not in the right order".into(),
+					),
+					Ordering::Equal => {}
 				}
+				self.decide(ordering, left_path, right_path)
 			}
-
-			if parsing_second {
-				parsing_second = false;
-				assert!(
-					first_packet_list_parse.len() == 1 && second_packet_list_parse.len() == 1,
-					"All lists resolved correctly"
+			(PacketDatum::List(first), PacketDatum::List(second)) => {
+				self.compare_lists(first, second, left_path, right_path, depth, None)
+			}
+			(PacketDatum::Integer(first), PacketDatum::List(second)) => {
+				let promoted = vec![left.clone()];
+				self.promote(Side::Left, *first, left_path, depth);
+				self.note(
+					depth + 1,
+					format!("Compare {} vs {}", PacketDatum::List(promoted.clone()), right),
 				);
-				packets.push((first_packet_list_parse[0].clone(), second_packet_list_parse[0].clone()));
-				first_packet_list_parse = vec![Vec::new()];
-				second_packet_list_parse = vec![Vec::new()];
-			} else {
-				parsing_second = true;
+				self.compare_lists(&promoted, second, left_path, right_path, depth + 1, Some(Side::Left))
+			}
+			(PacketDatum::List(first), PacketDatum::Integer(second)) => {
+				let promoted = vec![right.clone()];
+				self.promote(Side::Right, *second, right_path, depth);
+				self.note(
+					depth + 1,
+					format!("Compare {} vs {}", left, PacketDatum::List(promoted.clone())),
+				);
+				self.compare_lists(first, &promoted, left_path, right_path, depth + 1, Some(Side::Right))
 			}
 		}
-		packets.push((first_packet_list_parse[0].clone(), second_packet_list_parse[0].clone()));
+	}
 
-		packets
-	};
+	fn promote(&mut self, side: Side, value: i32, path: &[usize], depth: usize) {
+		self.note(
+			depth + 1,
+			format!("Mixed types; convert {} to [{}] and retry comparison", side, value),
+		);
+		self.promotions.push(Promotion {
+			side,
+			path: PacketPath(path.to_vec()),
+			value,
+		});
+	}
 
-	let mut index_sum = 0;
-	for (packet_index, (first_packet, second_packet)) in packets.iter().enumerate() {
-		for index in 0..(first_packet.len().max(second_packet.len())) {
-			if index >= first_packet.len() {
-				index_sum += packet_index + 1;
-				break;
+	// A promoted integer has no list of its own to index into, so only the other side's path goes deeper
+	fn compare_lists(
+		&mut self,
+		first: &[PacketDatum],
+		second: &[PacketDatum],
+		left_path: &mut Vec<usize>,
+		right_path: &mut Vec<usize>,
+		depth: usize,
+		promoted_side: Option<Side>,
+	) -> Ordering {
+		for (index, (first_datum, second_datum)) in first.iter().zip(second.iter()).enumerate() {
+			if !matches!(promoted_side, Some(Side::Left)) {
+				left_path.push(index);
 			}
-			if index >= second_packet.len() {
-				break;
+			if !matches!(promoted_side, Some(Side::Right)) {
+				right_path.push(index);
 			}
-			if let Some(in_order) = packet_data_in_order(&first_packet[index], &second_packet[index]) {
-				if in_order {
-					index_sum += packet_index + 1;
-				}
-				break;
+			let ordering = self.compare(first_datum, second_datum, left_path, right_path, depth + 1);
+			if !matches!(promoted_side, Some(Side::Left)) {
+				left_path.pop();
+			}
+			if !matches!(promoted_side, Some(Side::Right)) {
+				right_path.pop();
+			}
+			if ordering != Ordering::Equal {
+				return ordering;
+			}
+		}
+
+		let ordering = first.len().cmp(&second.len());
+		match ordering {
+			Ordering::Less => self.note(
+				depth + 1,
+				"Left side ran out of items, so inputs are in the right order".into(),
+			),
+			Ordering::Greater => self.note(
+				depth + 1,
+				"Right side ran out of items, so inputs are not in the right order".into(),
+			),
+			Ordering::Equal => {}
+		}
+		self.decide(ordering, left_path, right_path)
+	}
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+	let mut explain = false;
+	for arg in env::args().skip(1) {
+		match arg.as_str() {
+			"--explain" => explain = true,
+			_ => return Err(format!("unknown argument: {}", arg).into()),
+		}
+	}
+
+	let input_string = fs::read_to_string("input.txt")?;
+	let input_packets: Vec<PacketDatum> = input_string
+		.lines()
+		.enumerate()
+		.filter(|(_, line)| !line.trim().is_empty())
+		.map(|(line_index, line)| {
+			line.parse()
+				.map_err(|error| format!("Line {}: {}", line_index + 1, error))
+		})
+		.collect::<Result<_, String>>()?;
+	if !input_packets.len().is_multiple_of(2) {
+		return Err("The last packet has no pair".into());
+	}
+
+	if explain {
+		for (packet_index, pair) in input_packets.chunks(2).enumerate() {
+			let comparison = Comparison::new(&pair[0], &pair[1]);
+			println!("== Pair {} ==", packet_index + 1);
+			for line in comparison.trace.iter() {
+				println!("{}", line);
 			}
+			for promotion in comparison.promotions.iter() {
+				println!(
+					"Promoted {} {}: {} -> [{}]",
+					promotion.side, promotion.path, promotion.value, promotion.value
+				);
+			}
+			match comparison.decided_at {
+				Some((left_path, right_path)) => println!(
+					"{} at {} vs {}",
+					if comparison.ordering == Ordering::Less {
+						"In order"
+					} else {
+						"Out of order"
+					},
+					left_path,
+					right_path
+				),
+				None => println!("Packets are equal"),
+			}
+			println!();
+		}
+	}
+
+	let mut index_sum = 0;
+	for (packet_index, pair) in input_packets.chunks(2).enumerate() {
+		if pair[0] < pair[1] {
+			index_sum += packet_index + 1;
 		}
 	}
 
@@ -143,3 +386,149 @@ fn main() -> Result<(), Box<dyn Error>> {
 
 	Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const SAMPLE: &str = "[1,1,3,1,1]
+[1,1,5,1,1]
+
+[[1],[2,3,4]]
+[[1],4]
+
+[9]
+[[8,7,6]]
+
+[[4,4],4,4]
+[[4,4],4,4,4]
+
+[7,7,7,7]
+[7,7,7]
+
+[]
+[3]
+
+[[[]]]
+[[]]
+
+[1,[2,[3,[4,[5,6,7]]]],8,9]
+[1,[2,[3,[4,[5,6,0]]]],8,9]
+";
+
+	// Not random enough for anything but generating test packets
+	struct Xorshift(u64);
+
+	impl Xorshift {
+		fn next(&mut self) -> u64 {
+			self.0 ^= self.0 << 13;
+			self.0 ^= self.0 >> 7;
+			self.0 ^= self.0 << 17;
+			self.0
+		}
+
+		fn below(&mut self, limit: u64) -> u64 {
+			self.next() % limit
+		}
+	}
+
+	fn random_packet_datum(rng: &mut Xorshift, depth: usize) -> PacketDatum {
+		if depth > 0 && rng.below(3) == 0 {
+			return PacketDatum::Integer(rng.next() as i32);
+		}
+		let length = if depth < 5 { rng.below(5) } else { 0 };
+		PacketDatum::List((0..length).map(|_| random_packet_datum(rng, depth + 1)).collect())
+	}
+
+	fn sample_packets() -> Vec<PacketDatum> {
+		SAMPLE
+			.lines()
+			.filter(|line| !line.is_empty())
+			.map(|line| line.parse().unwrap())
+			.collect()
+	}
+
+	#[test]
+	fn sample_packets_print_as_written() {
+		for line in SAMPLE.lines().filter(|line| !line.is_empty()) {
+			assert_eq!(line.parse::<PacketDatum>().unwrap().to_string(), line);
+		}
+		assert_eq!(" [ 1 , [ ] ] ".parse::<PacketDatum>().unwrap().to_string(), "[1,[]]");
+	}
+
+	// Compared as text, since packet equality treats 1 and [1] alike
+	#[test]
+	fn random_packets_round_trip() {
+		let mut rng = Xorshift(0x2545_f491_4f6c_dd1d);
+		for _ in 0..1000 {
+			let serialised = random_packet_datum(&mut rng, 0).to_string();
+			assert_eq!(serialised.parse::<PacketDatum>().unwrap().to_string(), serialised);
+		}
+	}
+
+	#[test]
+	fn parse_errors_report_offsets() {
+		let error = |packet: &str| packet.parse::<PacketDatum>().unwrap_err();
+		assert_eq!(error("[1,,2]"), "Expected [ or an integer at offset 3, found ,");
+		assert_eq!(error("[1"), "Expected , or ] at offset 2, found end of packet");
+		assert_eq!(error("[-]"), "Expected a digit at offset 2, found ]");
+		assert_eq!(error("[1]]"), "Unexpected trailing input at offset 3");
+		assert_eq!(error("[1] 2"), "Unexpected trailing input at offset 4");
+		assert_eq!(
+			error("[1,99999999999]"),
+			"Integer out of range at offset 3: 99999999999"
+		);
+	}
+
+	// Explaining a comparison has to reach the same decision as comparing the packets directly
+	#[test]
+	fn comparisons_match_packet_order() {
+		let mut rng = Xorshift(0x9e37_79b9_7f4a_7c15);
+		let random_pairs = (0..1000).map(|_| (random_packet_datum(&mut rng, 0), random_packet_datum(&mut rng, 0)));
+		let sample_pairs: Vec<(PacketDatum, PacketDatum)> = sample_packets()
+			.chunks(2)
+			.map(|pair| (pair[0].clone(), pair[1].clone()))
+			.collect();
+		for (left, right) in sample_pairs.into_iter().chain(random_pairs) {
+			let comparison = Comparison::new(&left, &right);
+			assert_eq!(
+				comparison.ordering,
+				left.cmp(&right),
+				"comparing {} with {}",
+				left,
+				right
+			);
+			assert_eq!(
+				comparison.decided_at.is_some(),
+				comparison.ordering != Ordering::Equal,
+				"comparing {} with {}",
+				left,
+				right
+			);
+		}
+	}
+
+	#[test]
+	fn sample_decisions_are_located() {
+		let decisions: Vec<String> = sample_packets()
+			.chunks(2)
+			.map(|pair| match Comparison::new(&pair[0], &pair[1]).decided_at {
+				Some((left_path, right_path)) => format!("{} vs {}", left_path, right_path),
+				None => String::from("equal"),
+			})
+			.collect();
+		assert_eq!(
+			decisions,
+			vec![
+				"[2] vs [2]",
+				"[1][0] vs [1]",
+				"[0] vs [0][0]",
+				"packet vs packet",
+				"packet vs packet",
+				"packet vs packet",
+				"[0] vs [0]",
+				"[1][1][1][1][2] vs [1][1][1][1][2]",
+			]
+		);
+	}
+}
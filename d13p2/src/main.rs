@@ -1,16 +1,27 @@
 use std::cmp::Ordering;
+use std::env;
 use std::error::Error;
+use std::fmt;
 use std::fs;
+use std::slice;
+use std::str::FromStr;
 
-#[derive(Clone, Eq, PartialEq)]
+#[derive(Clone, Debug)]
 enum PacketDatum {
 	List(Vec<PacketDatum>),
 	Integer(i32),
 }
 
+// Packets are equal when neither is in order before the other, so [[1]], [1] and 1 are all equal
 impl Ord for PacketDatum {
 	fn cmp(&self, other: &Self) -> Ordering {
-		packet_data_in_order(self, other)
+		match (self, other) {
+			(Self::Integer(first), Self::Integer(second)) => first.cmp(second),
+			// Lists compare lexicographically, with a shorter list in order before a longer one it starts
+			(Self::List(first), Self::List(second)) => first.cmp(second),
+			(Self::Integer(_), Self::List(second)) => slice::from_ref(self).cmp(second.as_slice()),
+			(Self::List(first), Self::Integer(_)) => first.as_slice().cmp(slice::from_ref(other)),
+		}
 	}
 }
 
@@ -20,113 +31,342 @@ impl PartialOrd for PacketDatum {
 	}
 }
 
-fn packet_data_in_order(left: &PacketDatum, right: &PacketDatum) -> Ordering {
-	match (left, right) {
-		(PacketDatum::Integer(first), PacketDatum::Integer(second)) => first.cmp(second),
-		(PacketDatum::List(first), PacketDatum::List(second)) => {
-			for index in 0..(first.len().max(second.len())) {
-				if index >= first.len() {
-					return Ordering::Less;
-				}
-				if index >= second.len() {
-					return Ordering::Greater;
-				}
-				let val = packet_data_in_order(&first[index], &second[index]);
-				if val != Ordering::Equal {
-					return val;
+impl PartialEq for PacketDatum {
+	fn eq(&self, other: &Self) -> bool {
+		self.cmp(other) == Ordering::Equal
+	}
+}
+
+impl Eq for PacketDatum {}
+
+impl fmt::Display for PacketDatum {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::Integer(value) => write!(f, "{}", value),
+			Self::List(list) => {
+				write!(f, "[")?;
+				for (index, datum) in list.iter().enumerate() {
+					if index > 0 {
+						write!(f, ",")?;
+					}
+					write!(f, "{}", datum)?;
 				}
+				write!(f, "]")
 			}
-			Ordering::Equal
-		}
-		(PacketDatum::Integer(first), PacketDatum::List(_)) => {
-			packet_data_in_order(&PacketDatum::List(vec![PacketDatum::Integer(*first)]), right)
-		}
-		(PacketDatum::List(_), PacketDatum::Integer(second)) => {
-			packet_data_in_order(left, &PacketDatum::List(vec![PacketDatum::Integer(*second)]))
 		}
 	}
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-	let packets: Vec<Vec<PacketDatum>> = {
-		let input_string = fs::read_to_string("input.txt")?;
-		let mut packets = Vec::new();
-
-		let mut packet_list_parse: Vec<Vec<PacketDatum>> = vec![Vec::new()];
-		for packet_str in input_string.lines().filter(|s| !s.is_empty()) {
-			let Some(packet_str) = packet_str.strip_prefix('[') else {
-				panic!("Invalid packet format");
-			};
-			let Some(packet_str) = packet_str.strip_suffix(']') else {
-				panic!("Invalid packet format");
-			};
-
-			for entry in packet_str.split(',') {
-				let mut datum = entry;
-				while let Some(datum_stripped) = datum.strip_prefix('[') {
-					packet_list_parse.push(Vec::new());
-					datum = datum_stripped;
-				}
+// Recursive descent over the packet grammar, where a datum is an integer or a bracketed, comma separated list of data
+struct PacketParser<'a> {
+	input: &'a str,
+	offset: usize,
+}
 
-				let mut resolve_levels: u32 = 0;
-				while let Some(datum_stripped) = datum.strip_suffix(']') {
-					resolve_levels += 1;
-					datum = datum_stripped;
-				}
+impl PacketParser<'_> {
+	fn peek(&self) -> Option<char> {
+		self.input[self.offset..].chars().next()
+	}
 
-				if !datum.is_empty() {
-					packet_list_parse
-						.last_mut()
-						.unwrap()
-						.push(PacketDatum::Integer(datum.parse()?));
-				}
+	fn skip_whitespace(&mut self) {
+		while let Some(c) = self.peek().filter(|c| c.is_whitespace()) {
+			self.offset += c.len_utf8();
+		}
+	}
+
+	fn unexpected(&self, expected: &str) -> String {
+		match self.peek() {
+			Some(c) => format!("Expected {} at offset {}, found {}", expected, self.offset, c),
+			None => format!("Expected {} at offset {}, found end of packet", expected, self.offset),
+		}
+	}
+
+	fn parse_datum(&mut self) -> Result<PacketDatum, String> {
+		self.skip_whitespace();
+		match self.peek() {
+			Some('[') => self.parse_list(),
+			Some(c) if c == '-' || c.is_ascii_digit() => self.parse_integer(),
+			_ => Err(self.unexpected("[ or an integer")),
+		}
+	}
+
+	fn parse_list(&mut self) -> Result<PacketDatum, String> {
+		self.offset += 1; // Eat the [
+		let mut list: Vec<PacketDatum> = Vec::new();
+		self.skip_whitespace();
+		if self.peek() == Some(']') {
+			self.offset += 1;
+			return Ok(PacketDatum::List(list));
+		}
 
-				for _ in 0..resolve_levels {
-					let resolve_list = packet_list_parse.pop().unwrap();
-					packet_list_parse
-						.last_mut()
-						.unwrap()
-						.push(PacketDatum::List(resolve_list));
+		loop {
+			list.push(self.parse_datum()?);
+			self.skip_whitespace();
+			match self.peek() {
+				Some(',') => self.offset += 1,
+				Some(']') => {
+					self.offset += 1;
+					return Ok(PacketDatum::List(list));
 				}
+				_ => return Err(self.unexpected(", or ]")),
 			}
+		}
+	}
 
-			assert!(packet_list_parse.len() == 1, "All lists resolved correctly");
-			packets.push(packet_list_parse[0].clone());
-			packet_list_parse = vec![Vec::new()];
+	fn parse_integer(&mut self) -> Result<PacketDatum, String> {
+		let start = self.offset;
+		if self.peek() == Some('-') {
+			self.offset += 1;
+		}
+		let digits_start = self.offset;
+		while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+			self.offset += 1;
+		}
+		if self.offset == digits_start {
+			return Err(self.unexpected("a digit"));
 		}
-		packets.push(packet_list_parse[0].clone());
 
-		packets.push(vec![PacketDatum::List(vec![PacketDatum::Integer(2)])]);
-		packets.push(vec![PacketDatum::List(vec![PacketDatum::Integer(6)])]);
+		let number = &self.input[start..self.offset];
+		number
+			.parse()
+			.map(PacketDatum::Integer)
+			.map_err(|_| format!("Integer out of range at offset {}: {}", start, number))
+	}
+}
 
-		packets.sort_unstable();
+impl FromStr for PacketDatum {
+	type Err = String;
 
-		packets
-	};
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let mut parser = PacketParser { input: s, offset: 0 };
+		let packet = parser.parse_datum()?;
+		parser.skip_whitespace();
+		if parser.offset < s.len() {
+			return Err(format!("Unexpected trailing input at offset {}", parser.offset));
+		}
+		Ok(packet)
+	}
+}
 
-	let mut marker_2_index: Option<usize> = None;
-	let mut marker_6_index: Option<usize> = None;
+// The 1-based position the packet would have among the sorted packets, ahead of any packets equal to it
+fn packet_rank(sorted_packets: &[SortedPacket], packet: &PacketDatum) -> usize {
+	sorted_packets.partition_point(|other| other.packet < *packet) + 1
+}
 
-	for (packet_index, packet) in packets.iter().enumerate() {
-		if packet.len() == 1 {
-			if let PacketDatum::List(packet_list) = &packet[0] {
-				if packet_list.len() == 1 {
-					if let PacketDatum::Integer(packet_int) = &packet_list[0] {
-						if *packet_int == 2 {
-							marker_2_index = Some(packet_index);
-						} else if *packet_int == 6 {
-							marker_6_index = Some(packet_index);
-						}
-					}
-				}
+struct SortedPacket {
+	packet: PacketDatum,
+	// Indices of the divider packets this is, empty for packets from the input
+	dividers: Vec<usize>,
+}
+
+// Dividers sort after any input packets equal to them. Removing duplicates keeps the first of each run of equal
+// packets, which takes over the dividers of the ones removed.
+fn sort_packets(input_packets: Vec<PacketDatum>, divider_packets: &[PacketDatum], dedup: bool) -> Vec<SortedPacket> {
+	let mut packets: Vec<SortedPacket> = input_packets
+		.into_iter()
+		.map(|packet| SortedPacket {
+			packet,
+			dividers: Vec::new(),
+		})
+		.collect();
+	packets.extend(
+		divider_packets
+			.iter()
+			.enumerate()
+			.map(|(divider_index, packet)| SortedPacket {
+				packet: packet.clone(),
+				dividers: vec![divider_index],
+			}),
+	);
+
+	// Stable, so equal packets stay in input order
+	packets.sort_by(|first, second| first.packet.cmp(&second.packet));
+	if dedup {
+		packets.dedup_by(|removed, kept| {
+			let equal = removed.packet == kept.packet;
+			if equal {
+				kept.dividers.append(&mut removed.dividers);
 			}
+			equal
+		});
+	}
+	packets
+}
+
+// The 1-based position of each divider packet in the sorted packets
+fn divider_ranks(sorted_packets: &[SortedPacket], divider_count: usize) -> Vec<usize> {
+	let mut ranks = vec![0; divider_count];
+	for (index, packet) in sorted_packets.iter().enumerate() {
+		for divider_index in packet.dividers.iter() {
+			ranks[*divider_index] = index + 1;
+		}
+	}
+	ranks
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+	let mut divider_packets: Vec<PacketDatum> = Vec::new();
+	let mut rank_packets: Vec<PacketDatum> = Vec::new();
+	let mut dedup = false;
+	let mut print_sorted = false;
+	let mut args = env::args().skip(1);
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--divider" => divider_packets.push(args.next().ok_or("--divider requires a packet")?.parse()?),
+			"--rank" => rank_packets.push(args.next().ok_or("--rank requires a packet")?.parse()?),
+			"--dedup" => dedup = true,
+			"--sorted" => print_sorted = true,
+			_ => return Err(format!("unknown argument: {}", arg).into()),
+		}
+	}
+	if divider_packets.is_empty() {
+		divider_packets = vec!["[[2]]".parse()?, "[[6]]".parse()?];
+	}
+
+	let input_string = fs::read_to_string("input.txt")?;
+	let input_packets: Vec<PacketDatum> = input_string
+		.lines()
+		.enumerate()
+		.filter(|(_, line)| !line.trim().is_empty())
+		.map(|(line_index, line)| {
+			line.parse()
+				.map_err(|error| format!("Line {}: {}", line_index + 1, error))
+		})
+		.collect::<Result<_, String>>()?;
+
+	let packets = sort_packets(input_packets, &divider_packets, dedup);
+
+	if print_sorted {
+		for packet in packets.iter() {
+			println!("{}", packet.packet);
 		}
 	}
+	for packet in rank_packets.iter() {
+		println!("{}: {}", packet, packet_rank(&packets, packet));
+	}
 
-	let marker_2_index = marker_2_index.unwrap();
-	let marker_6_index = marker_6_index.unwrap();
-	let index_product = marker_2_index * marker_6_index;
+	let index_product: usize = divider_ranks(&packets, divider_packets.len()).iter().product();
 	println!("{}", index_product);
 
 	Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const SAMPLE: &str = "[1,1,3,1,1]
+[1,1,5,1,1]
+
+[[1],[2,3,4]]
+[[1],4]
+
+[9]
+[[8,7,6]]
+
+[[4,4],4,4]
+[[4,4],4,4,4]
+
+[7,7,7,7]
+[7,7,7]
+
+[]
+[3]
+
+[[[]]]
+[[]]
+
+[1,[2,[3,[4,[5,6,7]]]],8,9]
+[1,[2,[3,[4,[5,6,0]]]],8,9]
+";
+
+	// Not random enough for anything but generating test packets
+	struct Xorshift(u64);
+
+	impl Xorshift {
+		fn next(&mut self) -> u64 {
+			self.0 ^= self.0 << 13;
+			self.0 ^= self.0 >> 7;
+			self.0 ^= self.0 << 17;
+			self.0
+		}
+
+		fn below(&mut self, limit: u64) -> u64 {
+			self.next() % limit
+		}
+	}
+
+	fn random_packet_datum(rng: &mut Xorshift, depth: usize) -> PacketDatum {
+		if depth > 0 && rng.below(3) == 0 {
+			return PacketDatum::Integer(rng.next() as i32);
+		}
+		let length = if depth < 5 { rng.below(5) } else { 0 };
+		PacketDatum::List((0..length).map(|_| random_packet_datum(rng, depth + 1)).collect())
+	}
+
+	#[test]
+	fn sample_packets_print_as_written() {
+		for line in SAMPLE.lines().filter(|line| !line.is_empty()) {
+			assert_eq!(line.parse::<PacketDatum>().unwrap().to_string(), line);
+		}
+		assert_eq!(" [ 1 , [ ] ] ".parse::<PacketDatum>().unwrap().to_string(), "[1,[]]");
+	}
+
+	// Compared as text, since packet equality treats 1 and [1] alike
+	#[test]
+	fn random_packets_round_trip() {
+		let mut rng = Xorshift(0x2545_f491_4f6c_dd1d);
+		for _ in 0..1000 {
+			let serialised = random_packet_datum(&mut rng, 0).to_string();
+			assert_eq!(serialised.parse::<PacketDatum>().unwrap().to_string(), serialised);
+		}
+	}
+
+	#[test]
+	fn parse_errors_report_offsets() {
+		let error = |packet: &str| packet.parse::<PacketDatum>().unwrap_err();
+		assert_eq!(error("[1,,2]"), "Expected [ or an integer at offset 3, found ,");
+		assert_eq!(error("[1"), "Expected , or ] at offset 2, found end of packet");
+		assert_eq!(error("[-]"), "Expected a digit at offset 2, found ]");
+		assert_eq!(error("[1]]"), "Unexpected trailing input at offset 3");
+		assert_eq!(error("[1] 2"), "Unexpected trailing input at offset 4");
+		assert_eq!(
+			error("[1,99999999999]"),
+			"Integer out of range at offset 3: 99999999999"
+		);
+	}
+
+	fn sample_packets() -> Vec<PacketDatum> {
+		SAMPLE
+			.lines()
+			.filter(|line| !line.is_empty())
+			.map(|line| line.parse().unwrap())
+			.collect()
+	}
+
+	fn default_dividers() -> Vec<PacketDatum> {
+		vec!["[[2]]".parse().unwrap(), "[[6]]".parse().unwrap()]
+	}
+
+	#[test]
+	fn sample_divider_ranks() {
+		let packets = sort_packets(sample_packets(), &default_dividers(), false);
+		assert_eq!(divider_ranks(&packets, 2), vec![10, 14]);
+	}
+
+	// [2] and [[[2]]] are equal to [[2]], so the divider goes after them, or takes the first one's place when removing
+	// duplicates
+	#[test]
+	fn dividers_rank_among_equal_packets() {
+		let mut input_packets = sample_packets();
+		input_packets.push("[2]".parse().unwrap());
+		input_packets.push("[[[2]]]".parse().unwrap());
+		let packets = sort_packets(input_packets.clone(), &default_dividers(), false);
+		assert_eq!(divider_ranks(&packets, 2), vec![12, 16]);
+		assert_eq!(packet_rank(&packets, &default_dividers()[0]), 10);
+
+		let packets = sort_packets(input_packets, &default_dividers(), true);
+		assert_eq!(packets[9].packet.to_string(), "[2]");
+		assert_eq!(divider_ranks(&packets, 2), vec![10, 14]);
+	}
+}
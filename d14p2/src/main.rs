@@ -1,7 +1,11 @@
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::env;
 use std::error::Error;
-use std::fs;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::str::FromStr;
+
+const ASCIICAST_FRAME_SECONDS: f64 = 0.05;
 
 #[derive(Clone, Copy, Eq, Hash, PartialEq)]
 struct Coordinate {
@@ -24,97 +28,613 @@ impl Coordinate {
 		Self { x, y }
 	}
 
-	fn descend(&self) -> [Self; 3] {
-		let down = Coordinate {
-			x: self.x,
-			y: self.y + 1,
-		};
-		let down_left = Coordinate {
-			x: self.x - 1,
-			y: self.y + 1,
-		};
-		let down_right = Coordinate {
-			x: self.x + 1,
-			y: self.y + 1,
-		};
-		[down, down_left, down_right]
+	fn offset(&self, x_offset: i32, y_offset: u32) -> Option<Self> {
+		Some(Self {
+			x: self.x.checked_add_signed(x_offset)?,
+			y: self.y + y_offset,
+		})
 	}
 }
 
-#[derive(Eq, PartialEq)]
+#[derive(Clone, Copy, Eq, PartialEq)]
 enum ObjectType {
 	Sand,
+	Water,
 	Rock,
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-	let paths: Vec<Vec<Coordinate>> = {
-		let input_string = fs::read_to_string("input.txt")?;
-		input_string
-			.lines()
-			.filter(|s| !s.is_empty())
-			.map(|line| {
-				line.split(" -> ")
-					.map(|point| {
-						let mut parts = point.split(',');
-						let x = parts.next().unwrap();
-						let y = parts.next().unwrap();
-						assert!(parts.next().is_none());
-						let x: u32 = x.parse().unwrap();
-						let y: u32 = y.parse().unwrap();
-						Coordinate { x, y }
-					})
-					.collect()
-			})
-			.collect()
-	};
+// Every cell from min_x to min_x + width and from the top of the cave down to height, stored row by row
+struct CaveGrid {
+	min_x: u32,
+	width: usize,
+	height: usize,
+	cells: Vec<Option<ObjectType>>,
+}
+
+impl CaveGrid {
+	fn new(min_x: u32, max_x: u32, max_y: u32) -> Self {
+		let width = (max_x - min_x + 1) as usize;
+		let height = max_y as usize + 1;
+		Self {
+			min_x,
+			width,
+			height,
+			cells: vec![None; width * height],
+		}
+	}
+
+	fn index(&self, coord: &Coordinate) -> Option<usize> {
+		let x = coord.x.checked_sub(self.min_x)? as usize;
+		let y = coord.y as usize;
+		(x < self.width && y < self.height).then_some(y * self.width + x)
+	}
+
+	fn get(&self, coord: &Coordinate) -> Option<ObjectType> {
+		self.index(coord).and_then(|index| self.cells[index])
+	}
 
-	let mut cave_objects: HashMap<Coordinate, ObjectType> = HashMap::new();
+	fn insert(&mut self, coord: Coordinate, object: ObjectType) {
+		let index = self.index(&coord).expect("Objects are only placed inside the cave");
+		self.cells[index] = Some(object);
+	}
 
-	for path in paths.iter() {
+	fn add_rock_path(&mut self, path: &[Coordinate]) {
 		let mut previous_point: Option<Coordinate> = None;
 		for point in path.iter() {
 			if let Some(prev_point) = previous_point.take() {
 				let mut next_point = prev_point;
 				loop {
 					next_point = next_point.move_toward(point);
-					cave_objects.insert(next_point, ObjectType::Rock);
+					self.insert(next_point, ObjectType::Rock);
 					if next_point == *point {
 						break;
 					}
 				}
 			} else {
-				cave_objects.insert(*point, ObjectType::Rock);
+				self.insert(*point, ObjectType::Rock);
 			}
 			previous_point = Some(*point);
 		}
 	}
+}
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum Grain {
+	// Falls straight down, or diagonally down to the left then the right
+	Sand,
+	// Falls straight down, or flows sideways, first trying left then right, without turning back
+	Water,
+}
+
+impl Grain {
+	fn object_type(&self) -> ObjectType {
+		match self {
+			Self::Sand => ObjectType::Sand,
+			Self::Water => ObjectType::Water,
+		}
+	}
+}
 
-	let lowest_rock = paths.iter().flatten().map(|coord| coord.y).max().unwrap();
+#[derive(Clone, Copy)]
+enum AbyssBehaviour {
+	// The first grain to fall into the abyss ends the simulation
+	Stop,
+	// Every later grain from the same source would follow the first, so that source stops, but the others carry on
+	DrainSource,
+}
 
-	let floor = lowest_rock + 2;
+impl FromStr for AbyssBehaviour {
+	type Err = String;
 
-	let mut sand_position = Coordinate { x: 500, y: 0 };
-	'sand_fall: loop {
-		let next_sand_positions = sand_position.descend();
-		for next_coord in next_sand_positions.iter() {
-			if next_coord.y == floor {
-				break;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"stop" => Ok(Self::Stop),
+			"drain" => Ok(Self::DrainSource),
+			_ => Err(format!("Unknown abyss behaviour: {}", s)),
+		}
+	}
+}
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum Heading {
+	Down,
+	Left,
+	Right,
+}
+
+#[derive(Clone, Copy)]
+struct GrainPosition {
+	coord: Coordinate,
+	heading: Heading,
+}
+
+enum GrainStep {
+	Move(GrainPosition),
+	Rest,
+	Abyss,
+}
+
+enum GrainOutcome {
+	Rested,
+	Abyss,
+	SourceBlocked,
+}
+
+struct Bounds {
+	min_x: u32,
+	max_x: u32,
+	max_y: u32,
+}
+
+struct CaveConfig {
+	sources: Vec<Coordinate>,
+	// How far below the lowest rock the floor is, if there is one
+	floor_depth: Option<u32>,
+	abyss: AbyssBehaviour,
+	grain: Grain,
+}
+
+struct Cave {
+	grid: CaveGrid,
+	sources: Vec<Coordinate>,
+	floor: Option<u32>,
+	abyss: AbyssBehaviour,
+	grain: Grain,
+	// Where the last grain from each source went, so that the next one can carry on from where it came to rest
+	grain_paths: Vec<Vec<GrainPosition>>,
+	drained: Vec<bool>,
+	rest_order: Vec<Coordinate>,
+}
+
+impl Cave {
+	fn new(paths: &[Vec<Coordinate>], config: CaveConfig) -> Result<Self, String> {
+		if config.sources.is_empty() {
+			return Err("The cave needs at least one source".into());
+		}
+		if config.floor_depth == Some(0) {
+			return Err("The floor has to be below the lowest rock".into());
+		}
+
+		let points = || paths.iter().flatten().chain(config.sources.iter());
+		let lowest_rock = points().map(|coord| coord.y).max().unwrap();
+		let floor = config.floor_depth.map(|depth| lowest_rock + depth);
+		// Sand spreads at most one column sideways per row, so with a floor it can't get further than this from a
+		// source. Past the edge of the grid there's nothing to land on, so anything that gets there is lost.
+		let spread = floor.unwrap_or(0);
+		let min_x = points()
+			.map(|coord| coord.x)
+			.chain(config.sources.iter().map(|source| source.x.saturating_sub(spread)))
+			.min()
+			.unwrap()
+			.saturating_sub(1);
+		let max_x = points()
+			.map(|coord| coord.x)
+			.chain(config.sources.iter().map(|source| source.x + spread))
+			.max()
+			.unwrap() + 1;
+
+		let mut grid = CaveGrid::new(min_x, max_x, floor.map_or(lowest_rock, |floor| floor - 1));
+		for path in paths.iter() {
+			grid.add_rock_path(path);
+		}
+
+		let grain_paths = config
+			.sources
+			.iter()
+			.map(|source| match grid.get(source) {
+				Some(_) => Vec::new(),
+				None => vec![GrainPosition {
+					coord: *source,
+					heading: Heading::Down,
+				}],
+			})
+			.collect();
+		Ok(Self {
+			grid,
+			drained: vec![false; config.sources.len()],
+			sources: config.sources,
+			floor,
+			abyss: config.abyss,
+			grain: config.grain,
+			grain_paths,
+			rest_order: Vec::new(),
+		})
+	}
+
+	fn step(&self, position: GrainPosition) -> GrainStep {
+		let candidates: &[(i32, u32, Heading)] = match (self.grain, position.heading) {
+			(Grain::Sand, _) => &[(0, 1, Heading::Down), (-1, 1, Heading::Down), (1, 1, Heading::Down)],
+			(Grain::Water, Heading::Down) => &[(0, 1, Heading::Down), (-1, 0, Heading::Left), (1, 0, Heading::Right)],
+			(Grain::Water, Heading::Left) => &[(0, 1, Heading::Down), (-1, 0, Heading::Left)],
+			(Grain::Water, Heading::Right) => &[(0, 1, Heading::Down), (1, 0, Heading::Right)],
+		};
+
+		for (x_offset, y_offset, heading) in candidates.iter() {
+			let Some(coord) = position.coord.offset(*x_offset, *y_offset) else {
+				return GrainStep::Abyss;
+			};
+			if Some(coord.y) == self.floor {
+				continue;
+			}
+			if self.grid.index(&coord).is_none() {
+				return GrainStep::Abyss;
 			}
-			if !cave_objects.contains_key(next_coord) {
-				sand_position = *next_coord;
-				continue 'sand_fall;
+			if self.grid.get(&coord).is_none() {
+				return GrainStep::Move(GrainPosition {
+					coord,
+					heading: *heading,
+				});
 			}
 		}
-		cave_objects.insert(sand_position, ObjectType::Sand);
-		if sand_position.x == 500 && sand_position.y == 0 {
-			break;
+		GrainStep::Rest
+	}
+
+	fn drop_grain(&mut self, source_index: usize) -> GrainOutcome {
+		loop {
+			let Some(position) = self.grain_paths[source_index].last().copied() else {
+				return GrainOutcome::SourceBlocked;
+			};
+			match self.step(position) {
+				GrainStep::Move(next_position) => self.grain_paths[source_index].push(next_position),
+				GrainStep::Abyss => return GrainOutcome::Abyss,
+				GrainStep::Rest => {
+					self.grid.insert(position.coord, self.grain.object_type());
+					self.rest_order.push(position.coord);
+					// Other sources' grains went the same way up to where this one is now resting, but no further
+					for grain_path in self.grain_paths.iter_mut() {
+						if let Some(index) = grain_path.iter().position(|other| other.coord == position.coord) {
+							grain_path.truncate(index);
+						}
+					}
+					return GrainOutcome::Rested;
+				}
+			}
+		}
+	}
+
+	// Drops grains from each source in turn until they all stop, returning how many came to rest
+	fn fill(&mut self) -> usize {
+		let mut rested_count = 0;
+		loop {
+			let mut any_rested = false;
+			for source_index in 0..self.sources.len() {
+				if self.drained[source_index] {
+					continue;
+				}
+				match self.drop_grain(source_index) {
+					GrainOutcome::Rested => {
+						rested_count += 1;
+						any_rested = true;
+					}
+					GrainOutcome::Abyss => match self.abyss {
+						AbyssBehaviour::Stop => return rested_count,
+						AbyssBehaviour::DrainSource => self.drained[source_index] = true,
+					},
+					GrainOutcome::SourceBlocked => self.drained[source_index] = true,
+				}
+			}
+			if !any_rested {
+				return rested_count;
+			}
 		}
-		sand_position = Coordinate { x: 500, y: 0 };
 	}
 
-	let sand_count = cave_objects.values().filter(|o| **o == ObjectType::Sand).count();
-	println!("{}", sand_count);
+	// Sand can reach a cell when it can reach any of the three cells above it, unless the cell is rock. With a floor,
+	// nothing ever stops sand settling in a reachable cell, so the cells reachable above the floor are the ones that
+	// fill up.
+	fn reachable_cell_count(&self) -> Result<usize, String> {
+		let Some(floor) = self.floor else {
+			return Err("Reachable cells only all fill up when the cave has a floor".into());
+		};
+		if self.grain != Grain::Sand {
+			return Err("Reachable cells only all fill up with sand".into());
+		}
+
+		let top = self.sources.iter().map(|source| source.y).min().unwrap();
+		let mut reachable = vec![false; self.grid.width];
+		let mut reachable_count = 0;
+		for y in top..floor {
+			let row_above = reachable.clone();
+			for (column, cell) in reachable.iter_mut().enumerate() {
+				let coord = Coordinate {
+					x: self.grid.min_x + column as u32,
+					y,
+				};
+				let from_above = y > top
+					&& row_above[column.saturating_sub(1)..(column + 2).min(row_above.len())]
+						.iter()
+						.any(|reachable_above| *reachable_above);
+				let from_source = self.sources.contains(&coord);
+				*cell = (from_above || from_source) && self.grid.get(&coord).is_none();
+			}
+			reachable_count += reachable.iter().filter(|cell| **cell).count();
+		}
+		Ok(reachable_count)
+	}
+
+	fn symbol(&self, coord: &Coordinate) -> char {
+		match self.grid.get(coord) {
+			Some(ObjectType::Rock) => '#',
+			Some(ObjectType::Sand) => 'o',
+			Some(ObjectType::Water) => '~',
+			None if Some(coord.y) == self.floor => '#',
+			None if self.sources.contains(coord) => '+',
+			None => '.',
+		}
+	}
+
+	// The smallest box holding the sources and everything in the cave, down to the floor if there is one
+	fn bounds(&self) -> Bounds {
+		let mut bounds = Bounds {
+			min_x: self.sources[0].x,
+			max_x: self.sources[0].x,
+			max_y: self.floor.unwrap_or(self.sources[0].y),
+		};
+		let occupied = self.grid.cells.iter().enumerate().filter(|(_, cell)| cell.is_some());
+		let occupied_coords = occupied.map(|(index, _)| Coordinate {
+			x: self.grid.min_x + (index % self.grid.width) as u32,
+			y: (index / self.grid.width) as u32,
+		});
+		for coord in occupied_coords.chain(self.sources.iter().copied()) {
+			bounds.min_x = bounds.min_x.min(coord.x);
+			bounds.max_x = bounds.max_x.max(coord.x);
+			bounds.max_y = bounds.max_y.max(coord.y);
+		}
+		bounds
+	}
+
+	fn render(&self) -> String {
+		let bounds = self.bounds();
+		let mut output = String::new();
+		for y in 0..=bounds.max_y {
+			for x in bounds.min_x..=bounds.max_x {
+				output.push(self.symbol(&Coordinate { x, y }));
+			}
+			output.push('\n');
+		}
+		output
+	}
+
+	// Plays the grains back in the order they came to rest, within the bounds of the filled cave, passing on a frame
+	// before the first grain, after every so many grains and after the last one
+	fn replay<F>(&self, frame_every: usize, mut on_frame: F) -> Result<(), Box<dyn Error>>
+	where
+		F: FnMut(&[Vec<char>]) -> Result<(), Box<dyn Error>>,
+	{
+		let bounds = self.bounds();
+		let mut frame: Vec<Vec<char>> = (0..=bounds.max_y)
+			.map(|y| {
+				(bounds.min_x..=bounds.max_x)
+					.map(|x| match self.symbol(&Coordinate { x, y }) {
+						'o' | '~' if self.sources.contains(&Coordinate { x, y }) => '+',
+						'o' | '~' => '.',
+						symbol => symbol,
+					})
+					.collect()
+			})
+			.collect();
+
+		on_frame(&frame)?;
+		for (grain_index, coord) in self.rest_order.iter().enumerate() {
+			frame[coord.y as usize][(coord.x - bounds.min_x) as usize] = self.symbol(coord);
+			if (grain_index + 1) % frame_every == 0 || grain_index + 1 == self.rest_order.len() {
+				on_frame(&frame)?;
+			}
+		}
+		Ok(())
+	}
+}
+
+fn write_ppm(path: &str, pixels: &[Vec<[u8; 3]>]) -> io::Result<()> {
+	let mut file = BufWriter::new(File::create(path)?);
+	let height = pixels.len();
+	let width = pixels.first().map_or(0, |row| row.len());
+	write!(file, "P6\n{} {}\n255\n", width, height)?;
+	for row in pixels.iter() {
+		for pixel in row.iter() {
+			file.write_all(pixel)?;
+		}
+	}
+	file.flush()
+}
+
+fn frame_pixels(frame: &[Vec<char>]) -> Vec<Vec<[u8; 3]>> {
+	frame
+		.iter()
+		.map(|row| {
+			row.iter()
+				.map(|symbol| match symbol {
+					'#' => [128, 128, 128],
+					'o' => [210, 180, 110],
+					'~' => [60, 110, 230],
+					'+' => [230, 40, 40],
+					_ => [0, 0, 0],
+				})
+				.collect()
+		})
+		.collect()
+}
+
+fn write_ppm_frames(cave: &Cave, frame_every: usize, path_prefix: &str) -> Result<(), Box<dyn Error>> {
+	let mut frame_index = 0;
+	cave.replay(frame_every, |frame| {
+		write_ppm(&format!("{}{:05}.ppm", path_prefix, frame_index), &frame_pixels(frame))?;
+		frame_index += 1;
+		Ok(())
+	})
+}
+
+// An asciicast v2 recording: a JSON header line, then one line per frame that clears the terminal and redraws the cave
+fn write_asciicast(cave: &Cave, frame_every: usize, path: &str) -> Result<(), Box<dyn Error>> {
+	let bounds = cave.bounds();
+	let mut file = BufWriter::new(File::create(path)?);
+	writeln!(
+		file,
+		"{{\"version\": 2, \"width\": {}, \"height\": {}}}",
+		bounds.max_x - bounds.min_x + 1,
+		bounds.max_y + 1
+	)?;
+
+	let mut frame_index = 0;
+	cave.replay(frame_every, |frame| {
+		let screen: Vec<String> = frame.iter().map(|row| row.iter().collect()).collect();
+		writeln!(
+			file,
+			"[{:.2}, \"o\", \"\\u001b[H\\u001b[2J{}\\r\\n\"]",
+			frame_index as f64 * ASCIICAST_FRAME_SECONDS,
+			screen.join("\\r\\n")
+		)?;
+		frame_index += 1;
+		Ok(())
+	})?;
+	file.flush()?;
+	Ok(())
+}
+
+fn parse_point(point: &str) -> Result<Coordinate, String> {
+	let (x, y) = point
+		.split_once(',')
+		.ok_or_else(|| format!("Invalid point {}", point))?;
+	let x: u32 = x.trim().parse().map_err(|_| format!("Invalid x coordinate {}", x))?;
+	let y: u32 = y.trim().parse().map_err(|_| format!("Invalid y coordinate {}", y))?;
+	Ok(Coordinate { x, y })
+}
+
+fn parse_rock_paths(input: &str) -> Result<Vec<Vec<Coordinate>>, String> {
+	input
+		.lines()
+		.enumerate()
+		.filter(|(_, line)| !line.is_empty())
+		.map(|(line_index, line)| {
+			line.split(" -> ")
+				.map(|point| parse_point(point).map_err(|error| format!("Line {}: {}", line_index + 1, error)))
+				.collect()
+		})
+		.collect()
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+	let mut config = CaveConfig {
+		sources: Vec::new(),
+		floor_depth: Some(2),
+		abyss: AbyssBehaviour::Stop,
+		grain: Grain::Sand,
+	};
+	let mut analytic = false;
+	let mut print_render = false;
+	let mut frame_every: usize = 100;
+	let mut ppm_prefix: Option<String> = None;
+	let mut asciicast_path: Option<String> = None;
+	let mut args = env::args().skip(1);
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--source" => config
+				.sources
+				.push(parse_point(&args.next().ok_or("--source requires a coordinate")?)?),
+			"--floor" => config.floor_depth = Some(args.next().ok_or("--floor requires a depth")?.parse()?),
+			"--no-floor" => config.floor_depth = None,
+			"--abyss" => config.abyss = args.next().ok_or("--abyss requires stop or drain")?.parse()?,
+			"--water" => config.grain = Grain::Water,
+			"--render" => print_render = true,
+			"--frame-every" => frame_every = args.next().ok_or("--frame-every requires a grain count")?.parse()?,
+			"--ppm-frames" => ppm_prefix = Some(args.next().ok_or("--ppm-frames requires a path prefix")?),
+			"--asciicast" => asciicast_path = Some(args.next().ok_or("--asciicast requires an output path")?),
+			"--analytic" => analytic = true,
+			_ => return Err(format!("unknown argument: {}", arg).into()),
+		}
+	}
+	if frame_every == 0 {
+		return Err("--frame-every must be at least 1".into());
+	}
+	if config.sources.is_empty() {
+		config.sources.push(Coordinate { x: 500, y: 0 });
+	}
+
+	let paths: Vec<Vec<Coordinate>> = {
+		let input_string = fs::read_to_string("input.txt")?;
+		parse_rock_paths(&input_string)?
+	};
+
+	let mut cave = Cave::new(&paths, config)?;
+
+	if analytic && (print_render || ppm_prefix.is_some() || asciicast_path.is_some()) {
+		return Err("Rendering the cave needs the grains to be simulated, not counted".into());
+	}
+	let grain_count = if analytic {
+		cave.reachable_cell_count()?
+	} else {
+		cave.fill()
+	};
+	if print_render {
+		print!("{}", cave.render());
+	}
+	if let Some(prefix) = ppm_prefix {
+		write_ppm_frames(&cave, frame_every, &prefix)?;
+	}
+	if let Some(path) = asciicast_path {
+		write_asciicast(&cave, frame_every, &path)?;
+	}
+
+	println!("{}", grain_count);
 
 	Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const SAMPLE: &str = "498,4 -> 498,6 -> 496,6
+503,4 -> 502,4 -> 502,9 -> 494,9
+";
+
+	fn sample_cave(sources: &[(u32, u32)], floor_depth: Option<u32>) -> Cave {
+		let config = CaveConfig {
+			sources: sources.iter().map(|(x, y)| Coordinate { x: *x, y: *y }).collect(),
+			floor_depth,
+			abyss: AbyssBehaviour::Stop,
+			grain: Grain::Sand,
+		};
+		Cave::new(&parse_rock_paths(SAMPLE).unwrap(), config).unwrap()
+	}
+
+	#[test]
+	fn sample_sand_before_the_abyss() {
+		assert_eq!(sample_cave(&[(500, 0)], None).fill(), 24);
+	}
+
+	#[test]
+	fn sample_sand_until_the_source_is_blocked() {
+		let mut cave = sample_cave(&[(500, 0)], Some(2));
+		assert_eq!(cave.reachable_cell_count(), Ok(93));
+		assert_eq!(cave.fill(), 93);
+	}
+
+	#[test]
+	fn reachable_cells_match_simulation() {
+		let source_sets: [&[(u32, u32)]; 4] = [
+			&[(500, 0)],
+			&[(500, 0), (490, 0)],
+			&[(500, 0), (497, 3), (505, 2)],
+			&[(500, 5), (480, 1), (520, 0)],
+		];
+		for sources in source_sets {
+			for floor_depth in 1..=4 {
+				let mut cave = sample_cave(sources, Some(floor_depth));
+				let reachable_count = cave.reachable_cell_count().unwrap();
+				assert_eq!(
+					reachable_count,
+					cave.fill(),
+					"sources {:?}, floor {}",
+					sources,
+					floor_depth
+				);
+			}
+		}
+	}
+
+	#[test]
+	fn reachable_cells_need_sand_and_a_floor() {
+		assert!(sample_cave(&[(500, 0)], None).reachable_cell_count().is_err());
+	}
+}
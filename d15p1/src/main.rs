@@ -1,8 +1,9 @@
 use std::collections::HashSet;
+use std::env;
 use std::error::Error;
 use std::fs;
 
-const TARGET_Y_LEVEL: i32 = 2000000;
+const DEFAULT_TARGET_Y_LEVEL: i32 = 2000000;
 
 #[derive(Clone, Copy, Eq, Hash, PartialEq)]
 struct Coordinate {
@@ -19,9 +20,60 @@ impl Sensor {
 	fn beacon_distance(&self) -> u32 {
 		self.position.x.abs_diff(self.closest_beacon.x) + self.position.y.abs_diff(self.closest_beacon.y)
 	}
+
+	// The inclusive range of x the sensor can sense on row y, if it reaches that far
+	fn row_interval(&self, y: i32) -> Option<(i32, i32)> {
+		let remaining_distance = self.beacon_distance().checked_sub(self.position.y.abs_diff(y))?;
+		let remaining_distance: i32 = remaining_distance.try_into().ok()?;
+		Some((
+			self.position.x - remaining_distance,
+			self.position.x + remaining_distance,
+		))
+	}
+}
+
+// Sorts inclusive intervals and joins any that overlap or touch, leaving them disjoint
+fn merge_intervals(mut intervals: Vec<(i32, i32)>) -> Vec<(i32, i32)> {
+	intervals.sort_unstable();
+	let mut merged: Vec<(i32, i32)> = Vec::with_capacity(intervals.len());
+	for (start, end) in intervals {
+		match merged.last_mut() {
+			Some((_, last_end)) if i64::from(start) <= i64::from(*last_end) + 1 => *last_end = (*last_end).max(end),
+			_ => merged.push((start, end)),
+		}
+	}
+	merged
+}
+
+// The number of positions on row y that can't hold a beacon, since a sensor would have picked it up instead
+fn row_coverage(sensors: &[Sensor], y: i32) -> u64 {
+	let covered = merge_intervals(sensors.iter().filter_map(|sensor| sensor.row_interval(y)).collect());
+	let covered_count: u64 = covered.iter().map(|(start, end)| start.abs_diff(*end) as u64 + 1).sum();
+
+	let beacons_on_row: HashSet<i32> = sensors
+		.iter()
+		.map(|sensor| sensor.closest_beacon)
+		.filter(|beacon| beacon.y == y)
+		.map(|beacon| beacon.x)
+		.collect();
+	let covered_beacons = beacons_on_row
+		.iter()
+		.filter(|x| covered.iter().any(|(start, end)| (start..=end).contains(x)))
+		.count() as u64;
+
+	covered_count - covered_beacons
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
+	let mut target_y_level = DEFAULT_TARGET_Y_LEVEL;
+	let mut args = env::args().skip(1);
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--row" => target_y_level = args.next().ok_or("--row requires a y coordinate")?.parse()?,
+			_ => return Err(format!("unknown argument: {}", arg).into()),
+		}
+	}
+
 	let sensors: Vec<Sensor> = {
 		let input_string = fs::read_to_string("input.txt")?;
 
@@ -84,21 +136,65 @@ fn main() -> Result<(), Box<dyn Error>> {
 		sensors
 	};
 
-	let beacons: HashSet<Coordinate> = sensors.iter().map(|sensor| sensor.closest_beacon).collect();
-	let mut cannot_contain: HashSet<i32> = HashSet::new();
-	for sensor in sensors.iter() {
-		let sensor_distance = sensor.beacon_distance();
-		let vertical_distance = sensor.position.y.abs_diff(TARGET_Y_LEVEL);
-		let remaining_distance = sensor_distance.saturating_sub(vertical_distance);
-		let remaining_distance: i32 = remaining_distance.try_into().unwrap();
-		for x in (sensor.position.x - remaining_distance)..=(sensor.position.x + remaining_distance) {
-			if beacons.contains(&Coordinate { x, y: TARGET_Y_LEVEL }) {
-				continue;
-			}
-			cannot_contain.insert(x);
-		}
-	}
-	println!("{}", cannot_contain.len());
+	println!("{}", row_coverage(&sensors, target_y_level));
 
 	Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// Sensor x and y, then closest beacon x and y
+	const SAMPLE: [[i32; 4]; 14] = [
+		[2, 18, -2, 15],
+		[9, 16, 10, 16],
+		[13, 2, 15, 3],
+		[12, 14, 10, 16],
+		[10, 20, 10, 16],
+		[14, 17, 10, 16],
+		[8, 7, 2, 10],
+		[2, 0, 2, 10],
+		[0, 11, 2, 10],
+		[20, 14, 25, 17],
+		[17, 20, 21, 22],
+		[16, 7, 15, 3],
+		[14, 3, 15, 3],
+		[20, 1, 15, 3],
+	];
+
+	fn sensors(readings: &[[i32; 4]]) -> Vec<Sensor> {
+		readings
+			.iter()
+			.map(|[sensor_x, sensor_y, beacon_x, beacon_y]| Sensor {
+				position: Coordinate {
+					x: *sensor_x,
+					y: *sensor_y,
+				},
+				closest_beacon: Coordinate {
+					x: *beacon_x,
+					y: *beacon_y,
+				},
+			})
+			.collect()
+	}
+
+	#[test]
+	fn sample_row_coverage() {
+		assert_eq!(row_coverage(&sensors(&SAMPLE), 10), 26);
+	}
+
+	#[test]
+	fn touching_intervals_merge() {
+		assert_eq!(merge_intervals(vec![(3, 9), (-2, 2)]), vec![(-2, 9)]);
+		assert_eq!(merge_intervals(vec![(4, 9), (-2, 2)]), vec![(-2, 2), (4, 9)]);
+		assert_eq!(merge_intervals(vec![(0, 10), (2, 3), (10, 12)]), vec![(0, 12)]);
+	}
+
+	// On row 0 the sensors cover -2..=2, 3..=9, 4..=6 and 4..=4, which merge into -2..=9 with beacons at 2 and 4
+	#[test]
+	fn beacons_inside_merged_intervals() {
+		let readings = [[0, 0, 2, 0], [6, 0, 6, 3], [5, -4, 4, 0], [4, 1, 4, 0]];
+		assert_eq!(row_coverage(&sensors(&readings), 0), 10);
+	}
+}
@@ -1,7 +1,11 @@
+use std::collections::HashSet;
+use std::env;
 use std::error::Error;
-use std::fs;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
 
-const MAX_COORDINATE: i64 = 4000000;
+const DEFAULT_MAX_COORDINATE: i64 = 4000000;
+const TUNING_FREQUENCY_MULTIPLIER: i64 = 4000000;
 
 #[derive(Clone, Copy, Eq, Hash, PartialEq)]
 struct Coordinate {
@@ -19,56 +23,299 @@ impl Sensor {
 		self.position.x.abs_diff(self.closest_beacon.x) + self.position.y.abs_diff(self.closest_beacon.y)
 	}
 
-	fn outside_border_coordinates(&self) -> Vec<Coordinate> {
+	// The lines just outside the sensor's range, in coordinates rotated by 45 degrees so that the edges of its diamond
+	// are lines of constant x + y or constant x - y
+	fn outside_border_lines(&self) -> ([i64; 2], [i64; 2]) {
 		let sensor_distance: i64 = self.beacon_distance().try_into().unwrap();
 		let sensor_distance = sensor_distance + 1;
-		let top = Coordinate {
-			x: self.position.x,
-			y: self.position.y + sensor_distance,
-		};
-		let mut border_coordinates: Vec<Coordinate> = Vec::new();
-		let mut current = top;
-		let right = Coordinate {
-			x: self.position.x + sensor_distance,
-			y: self.position.y,
-		};
-		while current != right {
-			current.x += 1;
-			current.y -= 1;
-			border_coordinates.push(current);
+		let sum = self.position.x + self.position.y;
+		let difference = self.position.x - self.position.y;
+		(
+			[sum - sensor_distance, sum + sensor_distance],
+			[difference - sensor_distance, difference + sensor_distance],
+		)
+	}
+
+	fn can_sense_coordinate(&self, coord: &Coordinate) -> bool {
+		self.position.x.abs_diff(coord.x) + self.position.y.abs_diff(coord.y) <= self.beacon_distance()
+	}
+
+	// The inclusive range of x the sensor can sense on row y, if it reaches that far
+	fn row_interval(&self, y: i64) -> Option<(i64, i64)> {
+		let remaining_distance = self.beacon_distance().checked_sub(self.position.y.abs_diff(y))?;
+		let remaining_distance: i64 = remaining_distance.try_into().ok()?;
+		Some((
+			self.position.x - remaining_distance,
+			self.position.x + remaining_distance,
+		))
+	}
+}
+
+// An inclusive box of positions
+#[derive(Clone, Copy)]
+struct Rectangle {
+	min_x: i64,
+	max_x: i64,
+	min_y: i64,
+	max_y: i64,
+}
+
+impl Rectangle {
+	fn area(&self) -> u64 {
+		(self.min_x.abs_diff(self.max_x) + 1) * (self.min_y.abs_diff(self.max_y) + 1)
+	}
+}
+
+fn parse_rectangle(s: &str) -> Result<Rectangle, String> {
+	let bounds: Vec<i64> = s
+		.split(',')
+		.map(|bound| bound.trim().parse().map_err(|_| format!("Invalid bound: {}", bound)))
+		.collect::<Result<_, _>>()?;
+	let [min_x, min_y, max_x, max_y] = bounds[..] else {
+		return Err(format!("Expected min x, min y, max x and max y: {}", s));
+	};
+	if min_x > max_x || min_y > max_y {
+		return Err(format!("The region is empty: {}", s));
+	}
+	Ok(Rectangle {
+		min_x,
+		max_x,
+		min_y,
+		max_y,
+	})
+}
+
+// Sorts inclusive intervals and joins any that overlap or touch, leaving them disjoint
+fn merge_intervals(mut intervals: Vec<(i64, i64)>) -> Vec<(i64, i64)> {
+	intervals.sort_unstable();
+	let mut merged: Vec<(i64, i64)> = Vec::with_capacity(intervals.len());
+	for (start, end) in intervals {
+		match merged.last_mut() {
+			Some((_, last_end)) if start <= *last_end + 1 => *last_end = (*last_end).max(end),
+			_ => merged.push((start, end)),
 		}
-		let bottom = Coordinate {
-			x: self.position.x,
-			y: self.position.y - sensor_distance,
-		};
-		while current != bottom {
-			current.x -= 1;
-			current.y -= 1;
-			border_coordinates.push(current);
+	}
+	merged
+}
+
+// The ranges of x between min_x and max_x on row y that no sensor can sense
+fn row_gaps(sensors: &[Sensor], y: i64, min_x: i64, max_x: i64) -> Vec<(i64, i64)> {
+	let covered = merge_intervals(sensors.iter().filter_map(|sensor| sensor.row_interval(y)).collect());
+	let mut gaps: Vec<(i64, i64)> = Vec::new();
+	let mut next_x = min_x;
+	for (start, end) in covered {
+		if start > next_x {
+			gaps.push((next_x, (start - 1).min(max_x)));
 		}
-		let left = Coordinate {
-			x: self.position.x - sensor_distance,
-			y: self.position.y,
-		};
-		while current != left {
-			current.x -= 1;
-			current.y += 1;
-			border_coordinates.push(current);
+		next_x = next_x.max(end + 1);
+		if next_x > max_x {
+			return gaps;
 		}
-		while current != top {
-			current.x += 1;
-			current.y += 1;
-			border_coordinates.push(current);
+	}
+	gaps.push((next_x, max_x));
+	gaps
+}
+
+// Every position in the region that no sensor can sense, as rectangles made by stacking identical gaps in neighbouring
+// rows
+fn uncovered_regions(sensors: &[Sensor], region: &Rectangle) -> Vec<Rectangle> {
+	let mut uncovered: Vec<Rectangle> = Vec::new();
+	let mut open_regions: Vec<Rectangle> = Vec::new();
+	for y in region.min_y..=region.max_y {
+		let mut continued_regions: Vec<Rectangle> = Vec::new();
+		for (start, end) in row_gaps(sensors, y, region.min_x, region.max_x) {
+			let open_index = open_regions
+				.iter()
+				.position(|open_region| open_region.min_x == start && open_region.max_x == end);
+			match open_index {
+				Some(index) => {
+					let mut open_region = open_regions.swap_remove(index);
+					open_region.max_y = y;
+					continued_regions.push(open_region);
+				}
+				None => continued_regions.push(Rectangle {
+					min_x: start,
+					max_x: end,
+					min_y: y,
+					max_y: y,
+				}),
+			}
 		}
-		border_coordinates
+		uncovered.append(&mut open_regions);
+		open_regions = continued_regions;
 	}
+	uncovered.append(&mut open_regions);
 
-	fn can_sense_coordinate(&self, coord: &Coordinate) -> bool {
-		self.position.x.abs_diff(coord.x) + self.position.y.abs_diff(coord.y) <= self.beacon_distance()
+	uncovered.sort_unstable_by_key(|uncovered_region| (uncovered_region.min_y, uncovered_region.min_x));
+	uncovered
+}
+
+fn write_svg(path: &str, sensors: &[Sensor], region: &Rectangle, uncovered: &[Rectangle]) -> io::Result<()> {
+	let mut file = BufWriter::new(File::create(path)?);
+	let width = region.min_x.abs_diff(region.max_x) + 1;
+	let height = region.min_y.abs_diff(region.max_y) + 1;
+	// Positions are drawn as unit squares, and strokes keep their width however far the region is scaled down
+	writeln!(
+		file,
+		"<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"800\" height=\"{}\" viewBox=\"{} {} {} {}\">",
+		(800 * height / width).max(1),
+		region.min_x,
+		region.min_y,
+		width,
+		height
+	)?;
+	writeln!(
+		file,
+		"<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"white\"/>",
+		region.min_x, region.min_y, width, height
+	)?;
+	for sensor in sensors.iter() {
+		let centre_x = sensor.position.x as f64 + 0.5;
+		let centre_y = sensor.position.y as f64 + 0.5;
+		let reach = sensor.beacon_distance() as f64 + 0.5;
+		writeln!(
+			file,
+			"<polygon points=\"{},{} {},{} {},{} {},{}\" fill=\"steelblue\" fill-opacity=\"0.3\" stroke=\"steelblue\" vector-effect=\"non-scaling-stroke\"/>",
+			centre_x,
+			centre_y - reach,
+			centre_x + reach,
+			centre_y,
+			centre_x,
+			centre_y + reach,
+			centre_x - reach,
+			centre_y
+		)?;
+	}
+	for sensor in sensors.iter() {
+		for (coord, colour) in [(sensor.position, "navy"), (sensor.closest_beacon, "orange")] {
+			writeln!(
+				file,
+				"<rect x=\"{}\" y=\"{}\" width=\"1\" height=\"1\" fill=\"{}\" stroke=\"{}\" stroke-width=\"4\" vector-effect=\"non-scaling-stroke\"/>",
+				coord.x, coord.y, colour, colour
+			)?;
+		}
+	}
+	for uncovered_region in uncovered.iter() {
+		writeln!(
+			file,
+			"<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"red\" stroke=\"red\" stroke-width=\"2\" vector-effect=\"non-scaling-stroke\"/>",
+			uncovered_region.min_x,
+			uncovered_region.min_y,
+			uncovered_region.min_x.abs_diff(uncovered_region.max_x) + 1,
+			uncovered_region.min_y.abs_diff(uncovered_region.max_y) + 1
+		)?;
+	}
+	writeln!(file, "</svg>")?;
+	file.flush()
+}
+
+// Every position in the search area that no sensor can sense, checked a row at a time. Stops after the second one, since
+// that's enough to know the beacon can't be located.
+fn scan_uncovered_positions(sensors: &[Sensor], max_coordinate: i64) -> Vec<Coordinate> {
+	let mut found: Vec<Coordinate> = Vec::new();
+	for y in 0..=max_coordinate {
+		for (start, end) in row_gaps(sensors, y, 0, max_coordinate) {
+			for x in start..=end {
+				found.push(Coordinate { x, y });
+				if found.len() > 1 {
+					return found;
+				}
+			}
+		}
+	}
+	found
+}
+
+// A lone position no sensor can sense has every neighbour in range of some sensor, so it sits on the lines just outside
+// those sensors' ranges, or on the edge of the search area. Usually it's where two of those lines cross, where one of
+// them meets the edge, or in a corner, and checking just those candidates finds it without looking at the positions in
+// between. When every sensor boxing it in shares the same line, though, none of those lines cross there, so if no
+// candidate is free the whole search area is scanned instead.
+fn locate_distress_beacon(sensors: &[Sensor], max_coordinate: i64) -> Result<Coordinate, String> {
+	let mut sum_lines: HashSet<i64> = HashSet::new();
+	let mut difference_lines: HashSet<i64> = HashSet::new();
+	for sensor in sensors.iter() {
+		let (sums, differences) = sensor.outside_border_lines();
+		sum_lines.extend(sums);
+		difference_lines.extend(differences);
+	}
+
+	let mut candidates: HashSet<Coordinate> = HashSet::new();
+	for x in [0, max_coordinate] {
+		for y in [0, max_coordinate] {
+			candidates.insert(Coordinate { x, y });
+		}
+	}
+	for sum in sum_lines.iter() {
+		for difference in difference_lines.iter() {
+			// The lines only cross on a whole position when x + y and x - y are both even or both odd
+			if (sum + difference) % 2 == 0 {
+				candidates.insert(Coordinate {
+					x: (sum + difference) / 2,
+					y: (sum - difference) / 2,
+				});
+			}
+		}
+	}
+	for edge in [0, max_coordinate] {
+		for sum in sum_lines.iter() {
+			candidates.insert(Coordinate { x: edge, y: sum - edge });
+			candidates.insert(Coordinate { x: sum - edge, y: edge });
+		}
+		for difference in difference_lines.iter() {
+			candidates.insert(Coordinate {
+				x: edge,
+				y: edge - difference,
+			});
+			candidates.insert(Coordinate {
+				x: difference + edge,
+				y: edge,
+			});
+		}
+	}
+
+	let mut found: Vec<Coordinate> = candidates
+		.into_iter()
+		.filter(|coord| coord.x >= 0 && coord.x <= max_coordinate && coord.y >= 0 && coord.y <= max_coordinate)
+		.filter(|coord| !sensors.iter().any(|sensor| sensor.can_sense_coordinate(coord)))
+		.collect();
+	if found.is_empty() {
+		found = scan_uncovered_positions(sensors, max_coordinate);
+	}
+	match found.len() {
+		0 => Err(format!(
+			"Every position up to {} is in range of a sensor",
+			max_coordinate
+		)),
+		1 => Ok(found.remove(0)),
+		// Only candidates were checked, so there may be more positions in between them
+		count => Err(format!(
+			"At least {} positions are out of range of every sensor, so the beacon could be at any of them",
+			count
+		)),
 	}
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
+	let mut max_coordinate = DEFAULT_MAX_COORDINATE;
+	let mut region: Option<Rectangle> = None;
+	let mut list_uncovered = false;
+	let mut svg_path: Option<String> = None;
+	let mut args = env::args().skip(1);
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--max" => max_coordinate = args.next().ok_or("--max requires a coordinate")?.parse()?,
+			"--region" => {
+				region = Some(parse_rectangle(
+					&args.next().ok_or("--region requires min x,min y,max x,max y")?,
+				)?)
+			}
+			"--uncovered" => list_uncovered = true,
+			"--svg" => svg_path = Some(args.next().ok_or("--svg requires an output path")?),
+			_ => return Err(format!("unknown argument: {}", arg).into()),
+		}
+	}
+
 	let sensors: Vec<Sensor> = {
 		let input_string = fs::read_to_string("input.txt")?;
 
@@ -131,18 +378,117 @@ fn main() -> Result<(), Box<dyn Error>> {
 		sensors
 	};
 
-	for sensor in sensors.iter() {
-		let sensor_border_coordinates = sensor.outside_border_coordinates();
-		for coordinate in sensor_border_coordinates
-			.iter()
-			.filter(|coord| coord.x >= 0 && coord.x <= MAX_COORDINATE && coord.y >= 0 && coord.y <= MAX_COORDINATE)
-		{
-			if !sensors.iter().any(|s| s.can_sense_coordinate(coordinate)) {
-				let tuning_frequency = coordinate.x * 4000000 + coordinate.y;
-				println!("({}, {}) => {}", coordinate.x, coordinate.y, tuning_frequency);
+	if list_uncovered || svg_path.is_some() {
+		let region = region.unwrap_or(Rectangle {
+			min_x: 0,
+			max_x: max_coordinate,
+			min_y: 0,
+			max_y: max_coordinate,
+		});
+		let uncovered = uncovered_regions(&sensors, &region);
+		if list_uncovered {
+			for uncovered_region in uncovered.iter() {
+				println!(
+					"x {}..={}, y {}..={} ({} positions)",
+					uncovered_region.min_x,
+					uncovered_region.max_x,
+					uncovered_region.min_y,
+					uncovered_region.max_y,
+					uncovered_region.area()
+				);
 			}
+			let uncovered_area: u64 = uncovered.iter().map(|uncovered_region| uncovered_region.area()).sum();
+			let coverage = 100.0 * (region.area() - uncovered_area) as f64 / region.area() as f64;
+			println!("Coverage: {:.6}%", coverage);
+		}
+		if let Some(path) = svg_path {
+			write_svg(&path, &sensors, &region, &uncovered)?;
 		}
 	}
 
+	let beacon = locate_distress_beacon(&sensors, max_coordinate)?;
+	let tuning_frequency = beacon.x * TUNING_FREQUENCY_MULTIPLIER + beacon.y;
+	println!("({}, {}) => {}", beacon.x, beacon.y, tuning_frequency);
+
 	Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// Sensor x and y, then closest beacon x and y
+	const SAMPLE: [[i64; 4]; 14] = [
+		[2, 18, -2, 15],
+		[9, 16, 10, 16],
+		[13, 2, 15, 3],
+		[12, 14, 10, 16],
+		[10, 20, 10, 16],
+		[14, 17, 10, 16],
+		[8, 7, 2, 10],
+		[2, 0, 2, 10],
+		[0, 11, 2, 10],
+		[20, 14, 25, 17],
+		[17, 20, 21, 22],
+		[16, 7, 15, 3],
+		[14, 3, 15, 3],
+		[20, 1, 15, 3],
+	];
+
+	fn sensors(readings: &[[i64; 4]]) -> Vec<Sensor> {
+		readings
+			.iter()
+			.map(|[sensor_x, sensor_y, beacon_x, beacon_y]| Sensor {
+				position: Coordinate {
+					x: *sensor_x,
+					y: *sensor_y,
+				},
+				closest_beacon: Coordinate {
+					x: *beacon_x,
+					y: *beacon_y,
+				},
+			})
+			.collect()
+	}
+
+	#[test]
+	fn sample_distress_beacon() {
+		let beacon = locate_distress_beacon(&sensors(&SAMPLE), 20).unwrap();
+		assert_eq!((beacon.x, beacon.y), (14, 11));
+		assert_eq!(beacon.x * TUNING_FREQUENCY_MULTIPLIER + beacon.y, 56000011);
+	}
+
+	#[test]
+	fn fully_covered_search_area() {
+		assert_eq!(
+			locate_distress_beacon(&sensors(&[[5, 5, 5, 20]]), 10).err().unwrap(),
+			"Every position up to 10 is in range of a sensor"
+		);
+	}
+
+	// The only free position is boxed in by sensors whose ranges all end on the line x + y = 20, so it isn't where any
+	// two of their border lines cross
+	#[test]
+	fn beacon_between_sensors_sharing_a_border_line() {
+		let readings = [
+			[7, 7, 2, 7],
+			[13, 13, 18, 13],
+			[13, 7, 17, 7],
+			[7, 13, 3, 13],
+			[0, 20, -18, 20],
+			[20, 0, 38, 0],
+			[0, 0, -18, 0],
+			[12, 19, 21, 19],
+		];
+		let beacon = locate_distress_beacon(&sensors(&readings), 20).unwrap();
+		assert_eq!((beacon.x, beacon.y), (10, 10));
+	}
+
+	#[test]
+	fn ambiguous_search_area() {
+		assert_eq!(
+			locate_distress_beacon(&sensors(&SAMPLE), 30).err().unwrap(),
+			"At least 59 positions are out of range of every sensor, so the beacon could be at any of them"
+		);
+	}
+}
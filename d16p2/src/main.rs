@@ -1,330 +1,636 @@
-use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::env;
 use std::error::Error;
 use std::fs;
 
+const AGENT_COUNT: usize = 2;
+const MINUTES: u32 = 26;
+
 struct ValveData {
+	name: String,
 	flow_rate: u32,
 	to_valves: Vec<String>,
 }
 
-#[derive(Clone, Eq, PartialEq)]
-enum ValveLocation {
-	AtValve(String),
-	GoingToValve(String, u32),
+fn parse_valve(line: &str) -> Result<ValveData, String> {
+	let line_data = line
+		.strip_prefix("Valve ")
+		.ok_or_else(|| format!("Expected a valve: {}", line))?;
+	let (name, line_data) = line_data
+		.split_once(" has flow rate=")
+		.ok_or_else(|| format!("Expected a flow rate: {}", line))?;
+	let (flow_rate, line_data) = line_data
+		.split_once(';')
+		.ok_or_else(|| format!("Expected tunnels after the flow rate: {}", line))?;
+	let flow_rate: u32 = flow_rate
+		.parse()
+		.map_err(|_| format!("Invalid flow rate {}: {}", flow_rate, line))?;
+	let line_data = if let Some(data) = line_data.strip_prefix(" tunnels lead to valves ") {
+		data
+	} else if let Some(data) = line_data.strip_prefix(" tunnel leads to valve ") {
+		data
+	} else {
+		return Err(format!("Unexpected line data leading to valves: {}", line_data));
+	};
+	Ok(ValveData {
+		name: String::from(name),
+		flow_rate,
+		to_valves: line_data.split(", ").map(String::from).collect(),
+	})
 }
 
-#[derive(Clone, Eq, PartialEq)]
-struct ValveOpenProgress {
-	released_pressure: u32,
-	minutes_passed: u32,
-	open_valves: HashSet<String>,
-	current_valve: ValveLocation,
-	elephant_valve: ValveLocation,
+// Only valves that release pressure are worth walking to, so the graph keeps just those, numbered from 0 so that a set
+// of them fits in a u64, along with the shortest distance between every pair of them and from the start valve
+struct ValveGraph {
+	flow_rates: Vec<u32>,
+	// Indexed by the valve walked from, where the start valve comes after the working valves
+	distances: Vec<Vec<u32>>,
+	// Every valve, by its position in the input, for following the tunnels between working valves
+	valve_names: Vec<String>,
+	next_valves: Vec<Vec<usize>>,
+	working_valves: Vec<usize>,
+	start_valve: usize,
 }
 
-impl Ord for ValveOpenProgress {
-	fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-		self.released_pressure
-			.cmp(&other.released_pressure)
-			.then_with(|| self.minutes_passed.cmp(&other.minutes_passed))
-			.then_with(|| self.open_valves.len().cmp(&other.open_valves.len()))
+impl ValveGraph {
+	fn new(valves: &[ValveData], start_valve: &str) -> Result<Self, String> {
+		let valve_indices: HashMap<&str, usize> = valves
+			.iter()
+			.enumerate()
+			.map(|(index, valve)| (valve.name.as_str(), index))
+			.collect();
+		let start_index = *valve_indices
+			.get(start_valve)
+			.ok_or_else(|| format!("There's no valve {} to start from", start_valve))?;
+
+		// Floyd–Warshall over every valve, since the tunnels between working valves run through the others
+		let unreachable = u32::MAX / 2;
+		let mut all_distances = vec![vec![unreachable; valves.len()]; valves.len()];
+		// The first valve along a shortest path from one valve to another
+		let mut next_valves: Vec<Vec<usize>> = (0..valves.len()).map(|_| (0..valves.len()).collect()).collect();
+		for (index, valve) in valves.iter().enumerate() {
+			all_distances[index][index] = 0;
+			for to_valve in valve.to_valves.iter() {
+				let to_index = *valve_indices
+					.get(to_valve.as_str())
+					.ok_or_else(|| format!("Valve {} leads to unknown valve {}", valve.name, to_valve))?;
+				all_distances[index][to_index] = 1;
+			}
+		}
+		for via in 0..valves.len() {
+			for from in 0..valves.len() {
+				for to in 0..valves.len() {
+					let distance = all_distances[from][via] + all_distances[via][to];
+					if distance < all_distances[from][to] {
+						all_distances[from][to] = distance;
+						next_valves[from][to] = next_valves[from][via];
+					}
+				}
+			}
+		}
+
+		let working_valves: Vec<usize> = (0..valves.len()).filter(|index| valves[*index].flow_rate > 0).collect();
+		if working_valves.len() > u64::BITS as usize {
+			return Err(format!(
+				"{} valves release pressure, but at most {} are supported",
+				working_valves.len(),
+				u64::BITS
+			));
+		}
+		let distances = working_valves
+			.iter()
+			.chain([&start_index])
+			.map(|from| working_valves.iter().map(|to| all_distances[*from][*to]).collect())
+			.collect();
+
+		Ok(Self {
+			flow_rates: working_valves.iter().map(|index| valves[*index].flow_rate).collect(),
+			distances,
+			valve_names: valves.iter().map(|valve| valve.name.clone()).collect(),
+			next_valves,
+			working_valves,
+			start_valve: start_index,
+		})
 	}
-}
 
-impl PartialOrd for ValveOpenProgress {
-	fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-		Some(self.cmp(other))
+	fn start(&self) -> usize {
+		self.flow_rates.len()
 	}
 }
 
-struct TrackValveProgress {
-	current_valve: String,
-	visited: HashSet<String>,
+#[derive(Clone, Default)]
+struct AgentRoute {
+	released_pressure: u32,
+	// Working valves in the order they're opened
+	route: Vec<usize>,
 }
 
-impl TrackValveProgress {
-	fn new(start_valve: &str) -> Self {
-		let mut visited = HashSet::new();
-		visited.insert(String::from(start_valve));
-		Self {
-			current_valve: String::from(start_valve),
-			visited,
+// The most pressure one agent can release in the time given by opening exactly each set of valves it can get round to
+fn best_routes_by_open_valves(graph: &ValveGraph, minutes: u32) -> HashMap<u64, AgentRoute> {
+	fn visit(
+		graph: &ValveGraph,
+		position: usize,
+		minutes_left: u32,
+		open_valves: u64,
+		released_pressure: u32,
+		route: &mut Vec<usize>,
+		best_routes: &mut HashMap<u64, AgentRoute>,
+	) {
+		let best = best_routes.entry(open_valves).or_default();
+		if released_pressure > best.released_pressure {
+			*best = AgentRoute {
+				released_pressure,
+				route: route.clone(),
+			};
+		}
+
+		for next_valve in (0..graph.flow_rates.len()).filter(|valve| open_valves & (1 << valve) == 0) {
+			// Walking there and then a minute to open it, which only helps if there's time left for it to release
+			// any pressure
+			let minutes_taken = graph.distances[position][next_valve] + 1;
+			if minutes_taken < minutes_left {
+				let minutes_open = minutes_left - minutes_taken;
+				route.push(next_valve);
+				visit(
+					graph,
+					next_valve,
+					minutes_open,
+					open_valves | (1 << next_valve),
+					released_pressure + graph.flow_rates[next_valve] * minutes_open,
+					route,
+					best_routes,
+				);
+				route.pop();
+			}
 		}
 	}
+
+	let mut best_routes: HashMap<u64, AgentRoute> = HashMap::new();
+	visit(graph, graph.start(), minutes, 0, 0, &mut Vec::new(), &mut best_routes);
+	best_routes
 }
 
-struct ValveWithDistance {
-	valve: String,
-	distance: u32,
+// One agent's best routes within a time budget, along with their pressure from most to least
+struct BudgetRoutes {
+	best_pressure: Vec<(u64, u32)>,
+	best_routes: HashMap<u64, AgentRoute>,
 }
 
-fn build_valve_map_from_valve(
-	start_valve: &str,
-	valves_that_work: &HashSet<String>,
-	valves: &HashMap<String, ValveData>,
-	valve_map: &mut HashMap<String, Vec<ValveWithDistance>>,
-) {
-	let mut distance = 0;
-	let mut at_valves = vec![TrackValveProgress::new(start_valve)];
-	while !at_valves.is_empty() {
-		let mut new_at_valves = Vec::new();
-		for valve in at_valves.drain(..) {
-			for next_valve in valves
-				.get(&valve.current_valve)
-				.unwrap()
-				.to_valves
+struct Plan {
+	released_pressure: u32,
+	routes: Vec<Vec<usize>>,
+}
+
+// The most pressure agents can release working together, each opening different valves within their own time budget.
+// Agents with shorter budgets join in later, so that every budget runs out at the same time and every valve releases
+// pressure from when it's opened until then. That makes an agent's pressure depend only on its own budget. Combining the agents one at a time keeps the best pressure for every set of valves opened between them so far, along
+// with the valves each agent opened to get it.
+fn plan_for_agents(graph: &ValveGraph, budgets: &[u32]) -> Plan {
+	let mut best_by_budget: HashMap<u32, BudgetRoutes> = HashMap::new();
+	for budget in budgets.iter() {
+		best_by_budget.entry(*budget).or_insert_with(|| {
+			let best_routes = best_routes_by_open_valves(graph, *budget);
+			let mut best_pressure: Vec<(u64, u32)> = best_routes
 				.iter()
-				.filter(|v| !valve.visited.contains(*v))
-			{
-				let mut next_valve_track = TrackValveProgress {
-					current_valve: next_valve.clone(),
-					visited: valve.visited.clone(),
-				};
-				next_valve_track.visited.insert(next_valve_track.current_valve.clone());
-				new_at_valves.push(next_valve_track);
+				.map(|(open_valves, route)| (*open_valves, route.released_pressure))
+				.collect();
+			best_pressure.sort_unstable_by_key(|(_, pressure)| Reverse(*pressure));
+			BudgetRoutes {
+				best_pressure,
+				best_routes,
+			}
+		});
+	}
+
+	let mut combined_pressure: HashMap<u64, (u32, Vec<u64>)> = HashMap::from([(0, (0, Vec::new()))]);
+	for budget in budgets[..budgets.len() - 1].iter() {
+		let agent_pressure = &best_by_budget[budget].best_pressure;
+		let mut next_combined_pressure: HashMap<u64, (u32, Vec<u64>)> = HashMap::new();
+		for (open_valves, (released_pressure, agent_valves)) in combined_pressure.iter() {
+			for (next_agent_valves, agent_released_pressure) in agent_pressure.iter() {
+				if open_valves & next_agent_valves == 0 {
+					let best = next_combined_pressure
+						.entry(open_valves | next_agent_valves)
+						.or_default();
+					if released_pressure + agent_released_pressure >= best.0 {
+						let mut agent_valves = agent_valves.clone();
+						agent_valves.push(*next_agent_valves);
+						*best = (released_pressure + agent_released_pressure, agent_valves);
+					}
+				}
+			}
+		}
+		combined_pressure = next_combined_pressure;
+	}
+
+	// Only the total matters after the last agent, and with the agent's sets sorted by pressure the first one that
+	// doesn't overlap is the best
+	let agent_pressure = &best_by_budget[&budgets[budgets.len() - 1]].best_pressure;
+	let mut max_released_pressure = 0;
+	let mut best_agent_valves: Vec<u64> = vec![0; budgets.len()];
+	for (open_valves, (released_pressure, agent_valves)) in combined_pressure.iter() {
+		for (last_agent_valves, agent_released_pressure) in agent_pressure.iter() {
+			if released_pressure + agent_released_pressure <= max_released_pressure {
+				break;
 			}
-			let valve_map_entry = valve_map.entry(String::from(start_valve)).or_default();
-			if valve.current_valve != *start_valve
-				&& !valve_map_entry.iter().any(|ent| ent.valve == valve.current_valve)
-				&& valves_that_work.contains(&valve.current_valve)
-			{
-				let valve_data = ValveWithDistance {
-					valve: valve.current_valve.clone(),
-					distance,
-				};
-				valve_map_entry.push(valve_data);
+			if open_valves & last_agent_valves == 0 {
+				max_released_pressure = released_pressure + agent_released_pressure;
+				best_agent_valves = agent_valves.clone();
+				best_agent_valves.push(*last_agent_valves);
+				break;
 			}
 		}
-		distance += 1;
-		at_valves = new_at_valves;
+	}
+
+	let routes = budgets
+		.iter()
+		.zip(best_agent_valves.iter())
+		.map(|(budget, agent_valves)| best_by_budget[budget].best_routes[agent_valves].route.clone())
+		.collect();
+	Plan {
+		released_pressure: max_released_pressure,
+		routes,
 	}
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-	let valves: HashMap<String, ValveData> = {
-		let input_string = fs::read_to_string("input.txt")?;
+#[derive(Clone, Copy)]
+enum AgentAction {
+	Move(usize),
+	Open(usize),
+	Idle,
+}
 
-		let mut valves = HashMap::new();
+struct Minute {
+	// Working valves open during the minute, and whichever valve each agent is at by the end of it
+	open_valves: Vec<usize>,
+	released_pressure: u32,
+	total_released_pressure: u32,
+	actions: Vec<AgentAction>,
+	positions: Vec<usize>,
+}
 
-		for line in input_string.lines().filter(|s| !s.is_empty()) {
-			let line_data = line.strip_prefix("Valve ").unwrap();
-			let mut valve_name = String::new();
-			let mut line_chars = line_data.chars();
-			for c in line_chars.by_ref() {
-				if c == ' ' {
-					break;
+// Follows each agent along the tunnels of its route, a minute at a time. Agents with shorter budgets wait at the start
+// until only their budget is left, and a valve releases pressure from the minute after it's opened until the end.
+fn timeline(graph: &ValveGraph, budgets: &[u32], routes: &[Vec<usize>]) -> Vec<Minute> {
+	let agent_actions: Vec<Vec<AgentAction>> = routes
+		.iter()
+		.map(|route| {
+			let mut actions: Vec<AgentAction> = Vec::new();
+			let mut position = graph.start_valve;
+			for working_valve in route.iter() {
+				let target = graph.working_valves[*working_valve];
+				while position != target {
+					position = graph.next_valves[position][target];
+					actions.push(AgentAction::Move(position));
 				}
-				valve_name.push(c);
+				actions.push(AgentAction::Open(*working_valve));
 			}
-			let line_data: String = line_chars.collect();
-			let line_data = line_data.strip_prefix("has flow rate=").unwrap();
-			let mut parts = line_data.split(';');
-			let flow_rate = parts.next().unwrap();
-			let line_data = parts.next().unwrap();
-			assert!(parts.next().is_none());
-			let flow_rate: u32 = flow_rate.parse()?;
-			let line_data = if let Some(data) = line_data.strip_prefix(" tunnels lead to valves ") {
-				data
-			} else if let Some(data) = line_data.strip_prefix(" tunnel leads to valve ") {
-				data
+			actions
+		})
+		.collect();
+
+	let end = budgets.iter().copied().max().unwrap_or(0);
+	let mut opened_at: Vec<Option<u32>> = vec![None; graph.flow_rates.len()];
+	let mut positions: Vec<usize> = vec![graph.start_valve; budgets.len()];
+	let mut total_released_pressure = 0;
+	let mut minutes: Vec<Minute> = Vec::new();
+	for minute in 1..=end {
+		let mut open_valves: Vec<usize> = (0..graph.flow_rates.len())
+			.filter(|valve| opened_at[*valve].is_some_and(|opened| opened < minute))
+			.collect();
+		open_valves.sort_unstable_by_key(|valve| &graph.valve_names[graph.working_valves[*valve]]);
+		let released_pressure: u32 = open_valves.iter().map(|valve| graph.flow_rates[*valve]).sum();
+		total_released_pressure += released_pressure;
+
+		let mut actions: Vec<AgentAction> = Vec::new();
+		for (agent, budget) in budgets.iter().enumerate() {
+			let start = end - budget;
+			let action = if minute > start {
+				agent_actions[agent]
+					.get((minute - start) as usize - 1)
+					.copied()
+					.unwrap_or(AgentAction::Idle)
 			} else {
-				panic!("Unexpected line data leading to valves: {}", line_data);
+				AgentAction::Idle
 			};
-			let to_valves: Vec<String> = line_data.split(", ").map(String::from).collect();
-			let valve_data = ValveData { flow_rate, to_valves };
-			valves.insert(valve_name, valve_data);
+			match action {
+				AgentAction::Move(valve) => positions[agent] = valve,
+				AgentAction::Open(valve) => opened_at[valve] = Some(minute),
+				AgentAction::Idle => {}
+			}
+			actions.push(action);
 		}
 
-		valves
-	};
+		minutes.push(Minute {
+			open_valves,
+			released_pressure,
+			total_released_pressure,
+			actions,
+			positions: positions.clone(),
+		});
+	}
+	minutes
+}
+
+fn agent_name(agent: usize) -> String {
+	match agent {
+		0 => String::from("You"),
+		1 => String::from("The elephant"),
+		_ => format!("Elephant {}", agent),
+	}
+}
+
+// In the style of the puzzle's walkthrough, with a running total
+fn narrate(graph: &ValveGraph, minutes: &[Minute]) -> String {
+	let working_valve_name = |valve: &usize| graph.valve_names[graph.working_valves[*valve]].as_str();
+	let mut narration = String::new();
+	for (index, minute) in minutes.iter().enumerate() {
+		narration.push_str(&format!("== Minute {} ==\n", index + 1));
+		let names: Vec<&str> = minute.open_valves.iter().map(working_valve_name).collect();
+		match names.as_slice() {
+			[] => narration.push_str("No valves are open.\n"),
+			[name] => narration.push_str(&format!(
+				"Valve {} is open, releasing {} pressure.\n",
+				name, minute.released_pressure
+			)),
+			[first, second] => narration.push_str(&format!(
+				"Valves {} and {} are open, releasing {} pressure.\n",
+				first, second, minute.released_pressure
+			)),
+			[rest @ .., last] => narration.push_str(&format!(
+				"Valves {}, and {} are open, releasing {} pressure.\n",
+				rest.join(", "),
+				last,
+				minute.released_pressure
+			)),
+		}
+		for (agent, action) in minute.actions.iter().enumerate() {
+			// "You" takes the plural form of the verb
+			let verb_ending = if agent == 0 { "" } else { "s" };
+			match action {
+				AgentAction::Move(valve) => narration.push_str(&format!(
+					"{} move{} to valve {}.\n",
+					agent_name(agent),
+					verb_ending,
+					graph.valve_names[*valve]
+				)),
+				AgentAction::Open(valve) => narration.push_str(&format!(
+					"{} open{} valve {}.\n",
+					agent_name(agent),
+					verb_ending,
+					working_valve_name(valve)
+				)),
+				AgentAction::Idle => {}
+			}
+		}
+		narration.push_str(&format!(
+			"{} pressure released so far.\n\n",
+			minute.total_released_pressure
+		));
+	}
+	narration
+}
 
-	let valves_that_work: HashSet<String> = valves
+fn timeline_json(graph: &ValveGraph, budgets: &[u32], plan: &Plan, minutes: &[Minute]) -> String {
+	let quoted = |name: &str| format!("\"{}\"", name);
+	let agents: Vec<String> = budgets
 		.iter()
-		.filter(|(_, data)| data.flow_rate > 0)
-		.map(|(name, _)| name.clone())
+		.zip(plan.routes.iter())
+		.enumerate()
+		.map(|(agent, (budget, route))| {
+			let route: Vec<String> = route
+				.iter()
+				.map(|valve| quoted(&graph.valve_names[graph.working_valves[*valve]]))
+				.collect();
+			format!(
+				"{{\"name\":{},\"minutes\":{},\"route\":[{}]}}",
+				quoted(&agent_name(agent)),
+				budget,
+				route.join(",")
+			)
+		})
 		.collect();
-	let mut valve_map: HashMap<String, Vec<ValveWithDistance>> = HashMap::new();
-	build_valve_map_from_valve("AA", &valves_that_work, &valves, &mut valve_map);
-	for valve in valves_that_work.iter() {
-		build_valve_map_from_valve(valve, &valves_that_work, &valves, &mut valve_map);
-	}
+	let minutes: Vec<String> = minutes
+		.iter()
+		.enumerate()
+		.map(|(index, minute)| {
+			let open_valves: Vec<String> = minute
+				.open_valves
+				.iter()
+				.map(|valve| quoted(&graph.valve_names[graph.working_valves[*valve]]))
+				.collect();
+			let agents: Vec<String> = minute
+				.actions
+				.iter()
+				.zip(minute.positions.iter())
+				.map(|(action, position)| {
+					let action = match action {
+						AgentAction::Move(_) => "move",
+						AgentAction::Open(_) => "open",
+						AgentAction::Idle => "idle",
+					};
+					format!(
+						"{{\"position\":{},\"action\":\"{}\"}}",
+						quoted(&graph.valve_names[*position]),
+						action
+					)
+				})
+				.collect();
+			format!(
+				"{{\"minute\":{},\"open_valves\":[{}],\"released_pressure\":{},\"total_released_pressure\":{},\"agents\":[{}]}}",
+				index + 1,
+				open_valves.join(","),
+				minute.released_pressure,
+				minute.total_released_pressure,
+				agents.join(",")
+			)
+		})
+		.collect();
+	format!(
+		"{{\"released_pressure\":{},\"agents\":[{}],\"minutes\":[{}]}}\n",
+		plan.released_pressure,
+		agents.join(","),
+		minutes.join(",")
+	)
+}
 
-	let mut open_progress: BinaryHeap<ValveOpenProgress> = BinaryHeap::new();
-	for start_valve in valve_map.get("AA").unwrap().iter() {
-		for elephant_valve in valve_map.get("AA").unwrap().iter() {
-			let start_progress = ValveOpenProgress {
-				released_pressure: 0,
-				minutes_passed: 0,
-				open_valves: HashSet::new(),
-				current_valve: ValveLocation::GoingToValve(start_valve.valve.clone(), start_valve.distance),
-				elephant_valve: ValveLocation::GoingToValve(elephant_valve.valve.clone(), elephant_valve.distance),
-			};
-			open_progress.push(start_progress);
+fn parse_budgets(s: &str) -> Result<Vec<u32>, String> {
+	s.split(',')
+		.map(|budget| {
+			budget
+				.trim()
+				.parse()
+				.map_err(|_| format!("Invalid time budget: {}", budget))
+		})
+		.collect()
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+	let mut agent_count: usize = AGENT_COUNT;
+	let mut minutes: u32 = MINUTES;
+	let mut budgets: Option<Vec<u32>> = None;
+	let mut print_timeline = false;
+	let mut json_path: Option<String> = None;
+	let mut args = env::args().skip(1);
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--agents" => agent_count = args.next().ok_or("--agents requires an agent count")?.parse()?,
+			"--minutes" => minutes = args.next().ok_or("--minutes requires a time budget")?.parse()?,
+			"--budgets" => budgets = Some(parse_budgets(&args.next().ok_or("--budgets requires time budgets")?)?),
+			"--timeline" => print_timeline = true,
+			"--json" => json_path = Some(args.next().ok_or("--json requires an output path")?),
+			_ => return Err(format!("unknown argument: {}", arg).into()),
 		}
 	}
+	// Separate budgets for each agent take the place of the agent count and shared time budget
+	let budgets = budgets.unwrap_or_else(|| vec![minutes; agent_count]);
+	if budgets.is_empty() {
+		return Err("There has to be at least one agent".into());
+	}
 
-	let mut max_released_pressure = 0;
-	while let Some(mut progress) = open_progress.pop() {
-		if progress.open_valves == valves_that_work {
-			if progress.released_pressure > max_released_pressure {
-				println!(
-					"{} {:?} [{} minutes]",
-					progress.released_pressure, progress.open_valves, progress.minutes_passed
-				);
-				max_released_pressure = progress.released_pressure;
-			}
-			continue;
+	let valves: Vec<ValveData> = {
+		let input_string = fs::read_to_string("input.txt")?;
+		input_string
+			.lines()
+			.filter(|s| !s.is_empty())
+			.map(parse_valve)
+			.collect::<Result<_, _>>()?
+	};
+	let graph = ValveGraph::new(&valves, "AA")?;
+
+	let plan = plan_for_agents(&graph, &budgets);
+	if print_timeline || json_path.is_some() {
+		let minutes = timeline(&graph, &budgets, &plan.routes);
+		if print_timeline {
+			print!("{}", narrate(&graph, &minutes));
 		}
-		if progress.minutes_passed >= 26 {
-			if progress.released_pressure > max_released_pressure {
-				println!("{} {:?}", progress.released_pressure, progress.open_valves);
-				max_released_pressure = progress.released_pressure;
-			}
-			continue;
+		if let Some(path) = json_path {
+			fs::write(path, timeline_json(&graph, &budgets, &plan, &minutes))?;
 		}
+	}
 
-		match (&progress.current_valve, &progress.elephant_valve) {
-			(ValveLocation::AtValve(current_valve), ValveLocation::AtValve(elephant_valve)) => {
-				progress.minutes_passed += 1;
-				if progress.open_valves.insert(current_valve.clone()) {
-					progress.released_pressure +=
-						valves.get(current_valve).unwrap().flow_rate * (26 - progress.minutes_passed);
-				}
-				if progress.open_valves.insert(elephant_valve.clone()) {
-					progress.released_pressure +=
-						valves.get(elephant_valve).unwrap().flow_rate * (26 - progress.minutes_passed);
-				}
+	println!("{}", plan.released_pressure);
 
-				let mut next_current_valves = Vec::new();
-				for next_valve in valve_map
-					.get(current_valve)
-					.unwrap()
-					.iter()
-					.filter(|v| !progress.open_valves.contains(&v.valve))
-				{
-					let mut next_progress = progress.clone();
-					next_progress.current_valve =
-						ValveLocation::GoingToValve(next_valve.valve.clone(), next_valve.distance);
-					next_current_valves.push(next_progress);
-				}
-				let mut next_elephant_valves = Vec::new();
-				for next_progress in next_current_valves.drain(..) {
-					for next_valve in valve_map
-						.get(elephant_valve)
-						.unwrap()
-						.iter()
-						.filter(|v| !next_progress.open_valves.contains(&v.valve))
-					{
-						let mut next_progress = next_progress.clone();
-						next_progress.elephant_valve =
-							ValveLocation::GoingToValve(next_valve.valve.clone(), next_valve.distance);
-						next_elephant_valves.push(next_progress);
-					}
-				}
-				if next_elephant_valves.is_empty() {
-					open_progress.push(progress);
-				} else {
-					for valve_progress in next_elephant_valves.drain(..) {
-						open_progress.push(valve_progress);
-					}
-				}
-			}
-			(
-				ValveLocation::GoingToValve(current_valve, current_valve_distance),
-				ValveLocation::AtValve(elephant_valve),
-			) => {
-				progress.minutes_passed += 1;
-				let next_current_valve = if *current_valve_distance == 1 {
-					ValveLocation::AtValve(current_valve.clone())
-				} else {
-					ValveLocation::GoingToValve(current_valve.clone(), *current_valve_distance - 1)
-				};
-				if progress.open_valves.insert(elephant_valve.clone()) {
-					progress.released_pressure +=
-						valves.get(elephant_valve).unwrap().flow_rate * (26 - progress.minutes_passed);
-				}
+	Ok(())
+}
 
-				let mut added_progress = false;
-				for next_valve in valve_map
-					.get(elephant_valve)
-					.unwrap()
-					.iter()
-					.filter(|v| !progress.open_valves.contains(&v.valve) && v.valve != *current_valve)
-				{
-					let mut next_progress = progress.clone();
-					next_progress.elephant_valve =
-						ValveLocation::GoingToValve(next_valve.valve.clone(), next_valve.distance);
-					next_progress.current_valve = next_current_valve.clone();
-					open_progress.push(next_progress);
-					added_progress = true;
-				}
-				if !added_progress {
-					let mut next_progress = progress.clone();
-					next_progress.current_valve = next_current_valve;
-					open_progress.push(next_progress);
-				}
-			}
-			(
-				ValveLocation::AtValve(current_valve),
-				ValveLocation::GoingToValve(elephant_valve, elephant_valve_distance),
-			) => {
-				progress.minutes_passed += 1;
-				let next_elephant_valve = if *elephant_valve_distance == 1 {
-					ValveLocation::AtValve(elephant_valve.clone())
-				} else {
-					ValveLocation::GoingToValve(elephant_valve.clone(), *elephant_valve_distance - 1)
-				};
-				if progress.open_valves.insert(current_valve.clone()) {
-					progress.released_pressure +=
-						valves.get(current_valve).unwrap().flow_rate * (26 - progress.minutes_passed);
-				}
+#[cfg(test)]
+mod tests {
+	use super::*;
 
-				let mut added_progress = false;
-				for next_valve in valve_map
-					.get(current_valve)
-					.unwrap()
-					.iter()
-					.filter(|v| !progress.open_valves.contains(&v.valve) && v.valve != *elephant_valve)
-				{
-					let mut next_progress = progress.clone();
-					next_progress.current_valve =
-						ValveLocation::GoingToValve(next_valve.valve.clone(), next_valve.distance);
-					next_progress.elephant_valve = next_elephant_valve.clone();
-					open_progress.push(next_progress);
-					added_progress = true;
-				}
-				if !added_progress {
-					let mut next_progress = progress.clone();
-					next_progress.elephant_valve = next_elephant_valve;
-					open_progress.push(next_progress);
-				}
+	const SAMPLE: &str = "Valve AA has flow rate=0; tunnels lead to valves DD, II, BB
+Valve BB has flow rate=13; tunnels lead to valves CC, AA
+Valve CC has flow rate=2; tunnels lead to valves DD, BB
+Valve DD has flow rate=20; tunnels lead to valves CC, AA, EE
+Valve EE has flow rate=3; tunnels lead to valves FF, DD
+Valve FF has flow rate=0; tunnels lead to valves EE, GG
+Valve GG has flow rate=0; tunnels lead to valves FF, HH
+Valve HH has flow rate=22; tunnel leads to valve GG
+Valve II has flow rate=0; tunnels lead to valves AA, JJ
+Valve JJ has flow rate=21; tunnel leads to valve II
+";
+
+	fn sample_graph() -> ValveGraph {
+		let valves: Vec<ValveData> = SAMPLE.lines().map(|line| parse_valve(line).unwrap()).collect();
+		ValveGraph::new(&valves, "AA").unwrap()
+	}
+
+	// The most pressure one agent can release between the minute given and the end, opening only valves from the set
+	fn best_pressure_within(graph: &ValveGraph, position: usize, minute: u32, end: u32, allowed_valves: u64) -> u32 {
+		(0..graph.flow_rates.len())
+			.filter(|valve| allowed_valves & (1 << valve) != 0)
+			.filter_map(|valve| {
+				let opened = minute + graph.distances[position][valve] + 1;
+				(opened < end).then(|| {
+					graph.flow_rates[valve] * (end - opened)
+						+ best_pressure_within(graph, valve, opened, end, allowed_valves & !(1 << valve))
+				})
+			})
+			.max()
+			.unwrap_or(0)
+	}
+
+	// Tries every way of sharing the valves out between the agents, each starting once only its budget is left
+	fn plan_brute_force(graph: &ValveGraph, budgets: &[u32]) -> u32 {
+		let end = *budgets.iter().max().unwrap();
+		let valve_count = graph.flow_rates.len() as u32;
+		let mut best = 0;
+		for assignment in 0..budgets.len().pow(valve_count) {
+			let mut agent_valves = vec![0u64; budgets.len()];
+			let mut remaining = assignment;
+			for valve in 0..valve_count {
+				agent_valves[remaining % budgets.len()] |= 1 << valve;
+				remaining /= budgets.len();
 			}
-			(
-				ValveLocation::GoingToValve(current_valve, current_valve_distance),
-				ValveLocation::GoingToValve(elephant_valve, elephant_valve_distance),
-			) => {
-				let pass_minutes = (*current_valve_distance).min(*elephant_valve_distance);
-				progress.minutes_passed += pass_minutes;
-				let new_current_distance = *current_valve_distance - pass_minutes;
-				let new_elephant_distance = *elephant_valve_distance - pass_minutes;
-
-				let mut next_progress = progress.clone();
-				next_progress.current_valve = if new_current_distance == 0 {
-					ValveLocation::AtValve(current_valve.clone())
-				} else {
-					ValveLocation::GoingToValve(current_valve.clone(), new_current_distance)
-				};
-				next_progress.elephant_valve = if new_elephant_distance == 0 {
-					ValveLocation::AtValve(elephant_valve.clone())
-				} else {
-					ValveLocation::GoingToValve(elephant_valve.clone(), new_elephant_distance)
-				};
-				open_progress.push(next_progress);
+			let released_pressure = budgets
+				.iter()
+				.zip(agent_valves.iter())
+				.map(|(budget, valves)| best_pressure_within(graph, graph.start(), end - budget, end, *valves))
+				.sum();
+			best = best.max(released_pressure);
+		}
+		best
+	}
+
+	// Following the plan's routes has to release the pressure it claims, without two agents opening the same valve
+	fn assert_routes_release(graph: &ValveGraph, budgets: &[u32], plan: &Plan) {
+		let mut opened: Vec<usize> = Vec::new();
+		let mut released_pressure = 0;
+		for (budget, route) in budgets.iter().zip(plan.routes.iter()) {
+			let mut position = graph.start();
+			let mut minutes_left = *budget;
+			for valve in route.iter() {
+				assert!(!opened.contains(valve), "valve {} opened twice", valve);
+				opened.push(*valve);
+				minutes_left -= graph.distances[position][*valve] + 1;
+				released_pressure += graph.flow_rates[*valve] * minutes_left;
+				position = *valve;
 			}
 		}
+		assert_eq!(released_pressure, plan.released_pressure);
 	}
 
-	println!("{}", max_released_pressure);
+	#[test]
+	fn sample_plans() {
+		let graph = sample_graph();
+		for (budgets, expected) in [(vec![30], 1651), (vec![26, 26], 1707)] {
+			let plan = plan_for_agents(&graph, &budgets);
+			assert_eq!(plan.released_pressure, expected);
+			assert_routes_release(&graph, &budgets, &plan);
+		}
+	}
 
-	Ok(())
+	#[test]
+	fn mixed_budgets_match_brute_force() {
+		let graph = sample_graph();
+		for budgets in [vec![30, 10], vec![5, 26], vec![12, 26, 20], vec![8, 8, 30], vec![3]] {
+			let plan = plan_for_agents(&graph, &budgets);
+			assert_eq!(
+				plan.released_pressure,
+				plan_brute_force(&graph, &budgets),
+				"budgets {:?}",
+				budgets
+			);
+			assert_routes_release(&graph, &budgets, &plan);
+		}
+	}
+
+	// Valves stay open once opened, so the timeline's running total is the plan's
+	#[test]
+	fn timelines_release_the_planned_pressure() {
+		let graph = sample_graph();
+		for budgets in [vec![30], vec![26, 26], vec![30, 10], vec![12, 26, 20]] {
+			let plan = plan_for_agents(&graph, &budgets);
+			let minutes = timeline(&graph, &budgets, &plan.routes);
+			assert_eq!(minutes.len() as u32, *budgets.iter().max().unwrap());
+			assert_eq!(minutes.last().unwrap().total_released_pressure, plan.released_pressure);
+			for pair in minutes.windows(2) {
+				assert!(pair[0]
+					.open_valves
+					.iter()
+					.all(|valve| pair[1].open_valves.contains(valve)));
+			}
+		}
+	}
 }
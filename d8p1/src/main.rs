@@ -1,7 +1,103 @@
+use std::env;
 use std::error::Error;
-use std::fs;
+use std::fmt::Display;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+
+fn record_visibility<L>(grid: &[Vec<u8>], line: L, visible_trees: &mut [Vec<bool>])
+where
+	L: Iterator<Item = (usize, usize)>,
+{
+	let mut highest_tree: Option<u8> = None;
+	for (row_index, col_index) in line {
+		let height = grid[row_index][col_index];
+		if highest_tree.is_none_or(|highest| height > highest) {
+			highest_tree = Some(height);
+			visible_trees[row_index][col_index] = true;
+		}
+	}
+}
+
+fn visible_trees(grid: &[Vec<u8>]) -> Vec<Vec<bool>> {
+	let row_count = grid.len();
+	let col_count = grid[0].len();
+	let mut visible_trees = vec![vec![false; col_count]; row_count];
+
+	for row_index in 0..row_count {
+		record_visibility(grid, (0..col_count).map(|col| (row_index, col)), &mut visible_trees);
+		record_visibility(
+			grid,
+			(0..col_count).rev().map(|col| (row_index, col)),
+			&mut visible_trees,
+		);
+	}
+
+	for col_index in 0..col_count {
+		record_visibility(grid, (0..row_count).map(|row| (row, col_index)), &mut visible_trees);
+		record_visibility(
+			grid,
+			(0..row_count).rev().map(|row| (row, col_index)),
+			&mut visible_trees,
+		);
+	}
+
+	visible_trees
+}
+
+fn write_csv<T: Display>(path: &str, matrix: &[Vec<T>]) -> io::Result<()> {
+	let mut file = BufWriter::new(File::create(path)?);
+	for row in matrix.iter() {
+		let line: Vec<String> = row.iter().map(|value| value.to_string()).collect();
+		writeln!(file, "{}", line.join(","))?;
+	}
+	file.flush()
+}
+
+fn write_ppm(path: &str, pixels: &[Vec<[u8; 3]>]) -> io::Result<()> {
+	let mut file = BufWriter::new(File::create(path)?);
+	let height = pixels.len();
+	let width = pixels.first().map_or(0, |row| row.len());
+	write!(file, "P6\n{} {}\n255\n", width, height)?;
+	for row in pixels.iter() {
+		for pixel in row.iter() {
+			file.write_all(pixel)?;
+		}
+	}
+	file.flush()
+}
+
+fn visibility_map(grid: &[Vec<u8>], visible_trees: &[Vec<bool>]) -> Vec<Vec<[u8; 3]>> {
+	grid.iter()
+		.zip(visible_trees.iter())
+		.map(|(row, visible_row)| {
+			row.iter()
+				.zip(visible_row.iter())
+				.map(|(height, visible)| {
+					// Visible trees are green and hidden trees are grey, both brighter for taller trees
+					let brightness = 75 + *height * 20;
+					if *visible {
+						[0, brightness, 0]
+					} else {
+						[brightness / 2, brightness / 2, brightness / 2]
+					}
+				})
+				.collect()
+		})
+		.collect()
+}
 
 fn main() -> Result<(), Box<dyn Error>> {
+	let mut csv_path: Option<String> = None;
+	let mut ppm_path: Option<String> = None;
+	let mut args = env::args().skip(1);
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--csv" => csv_path = Some(args.next().ok_or("--csv requires an output path")?),
+			"--ppm" => ppm_path = Some(args.next().ok_or("--ppm requires an output path")?),
+			_ => return Err(format!("unknown argument: {}", arg).into()),
+		}
+	}
+
 	let grid: Vec<Vec<u8>> = {
 		let input_string = fs::read_to_string("input.txt")?;
 		let data: Vec<Vec<u8>> = input_string
@@ -11,65 +107,117 @@ fn main() -> Result<(), Box<dyn Error>> {
 		data
 	};
 
-	let mut visible_trees: Vec<Vec<bool>> = vec![vec![false; grid[0].len()]; grid.len()];
+	let visible_trees = visible_trees(&grid);
 
-	for row_index in 0..grid.len() {
-		let mut highest_tree = 0;
-		for col_index in 0..grid[row_index].len() {
-			if grid[row_index][col_index] > highest_tree {
-				highest_tree = grid[row_index][col_index];
-				visible_trees[row_index][col_index] = true;
-			}
-		}
+	if let Some(path) = csv_path {
+		let visibility_flags: Vec<Vec<u8>> = visible_trees
+			.iter()
+			.map(|row| row.iter().map(|visible| u8::from(*visible)).collect())
+			.collect();
+		write_csv(&path, &visibility_flags)?;
+	}
+	if let Some(path) = ppm_path {
+		write_ppm(&path, &visibility_map(&grid, &visible_trees))?;
+	}
 
-		highest_tree = 0;
-		for col_index in (0..grid[row_index].len()).rev() {
-			if grid[row_index][col_index] > highest_tree {
-				highest_tree = grid[row_index][col_index];
-				visible_trees[row_index][col_index] = true;
-			}
+	let total: usize = visible_trees
+		.iter()
+		.map(|row| row.iter().filter(|val| **val).count())
+		.sum();
+	println!("{}", total);
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const SAMPLE: [&str; 5] = ["30373", "25512", "65332", "33549", "35390"];
+
+	struct Xorshift(u64);
+
+	impl Xorshift {
+		fn below(&mut self, limit: u64) -> u64 {
+			self.0 ^= self.0 << 13;
+			self.0 ^= self.0 >> 7;
+			self.0 ^= self.0 << 17;
+			self.0 % limit
 		}
 	}
 
-	for col_index in 0..grid[0].len() {
-		let mut highest_tree = 0;
-		for row_index in 0..grid.len() {
-			if grid[row_index][col_index] > highest_tree {
-				highest_tree = grid[row_index][col_index];
-				visible_trees[row_index][col_index] = true;
-			}
+	fn parse_grid(rows: &[&str]) -> Vec<Vec<u8>> {
+		rows.iter().map(|row| row.bytes().map(|b| b - b'0').collect()).collect()
+	}
+
+	fn random_grid(rng: &mut Xorshift, row_count: usize, col_count: usize, max_height: u64) -> Vec<Vec<u8>> {
+		(0..row_count)
+			.map(|_| (0..col_count).map(|_| rng.below(max_height + 1) as u8).collect())
+			.collect()
+	}
+
+	// Includes single rows, single columns and grids where every tree has the same height
+	fn test_grids() -> Vec<Vec<Vec<u8>>> {
+		let mut rng = Xorshift(0x9e37_79b9_7f4a_7c15);
+		let mut grids = vec![parse_grid(&SAMPLE), vec![vec![5]], vec![vec![3; 9]; 7]];
+		for length in 1..=12 {
+			grids.push(random_grid(&mut rng, 1, length, 9));
+			grids.push(random_grid(&mut rng, length, 1, 9));
 		}
+		for _ in 0..200 {
+			let row_count = 1 + rng.below(15) as usize;
+			let col_count = 1 + rng.below(15) as usize;
+			let max_height = [0, 1, 3, 9][rng.below(4) as usize];
+			grids.push(random_grid(&mut rng, row_count, col_count, max_height));
+		}
+		grids
+	}
 
-		highest_tree = 0;
-		for row_index in (0..grid.len()).rev() {
-			if grid[row_index][col_index] > highest_tree {
-				highest_tree = grid[row_index][col_index];
-				visible_trees[row_index][col_index] = true;
+	fn visible_trees_brute_force(grid: &[Vec<u8>]) -> Vec<Vec<bool>> {
+		let mut visible_trees = Vec::with_capacity(grid.len());
+		for (row_index, row) in grid.iter().enumerate() {
+			let mut row_visibility = Vec::with_capacity(row.len());
+			for (col_index, height) in row.iter().enumerate() {
+				let visible_from_up = grid
+					.iter()
+					.take(row_index)
+					.all(|other_row| other_row[col_index] < *height);
+				let visible_from_left = row.iter().take(col_index).all(|other_height| *other_height < *height);
+				let visible_from_down = grid
+					.iter()
+					.skip(row_index + 1)
+					.all(|other_row| other_row[col_index] < *height);
+				let visible_from_right = row
+					.iter()
+					.skip(col_index + 1)
+					.all(|other_height| *other_height < *height);
+				row_visibility.push(visible_from_up || visible_from_left || visible_from_down || visible_from_right);
 			}
+			visible_trees.push(row_visibility);
 		}
-	}
 
-	for row in visible_trees.iter_mut() {
-		// each column
-		row[0] = true;
-		let row_len = row.len();
-		row[row_len - 1] = true;
+		visible_trees
 	}
 
-	for col in visible_trees[0].iter_mut() {
-		*col = true;
+	#[test]
+	fn sample_visible_tree_count() {
+		let visible_trees = visible_trees(&parse_grid(&SAMPLE));
+		let total: usize = visible_trees
+			.iter()
+			.map(|row| row.iter().filter(|val| **val).count())
+			.sum();
+		assert_eq!(total, 21);
 	}
 
-	let visible_trees_rows = visible_trees.len();
-	for col in visible_trees[visible_trees_rows - 1].iter_mut() {
-		*col = true;
+	#[test]
+	fn visible_trees_match_brute_force() {
+		for grid in test_grids() {
+			assert_eq!(
+				visible_trees(&grid),
+				visible_trees_brute_force(&grid),
+				"grid {:?}",
+				grid
+			);
+		}
 	}
-
-	let total: usize = visible_trees
-		.iter()
-		.map(|row| row.iter().filter(|val| **val).count())
-		.sum();
-	println!("{}", total);
-
-	Ok(())
 }
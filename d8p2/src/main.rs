@@ -1,7 +1,134 @@
+use std::env;
 use std::error::Error;
-use std::fs;
+use std::fmt::Display;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+struct ViewingDistances {
+	up: usize,
+	left: usize,
+	down: usize,
+	right: usize,
+}
+
+impl ViewingDistances {
+	fn scenic_score(&self) -> usize {
+		self.up * self.left * self.down * self.right
+	}
+}
+
+fn record_viewing_distances<L, R>(grid: &[Vec<u8>], line: L, mut record: R)
+where
+	L: Iterator<Item = (usize, usize)>,
+	R: FnMut(usize, usize, usize),
+{
+	// Trees that could still block the view of a later tree in the line, with strictly decreasing heights
+	let mut blocking_trees: Vec<(usize, u8)> = Vec::new();
+	for (position, (row_index, col_index)) in line.enumerate() {
+		let height = grid[row_index][col_index];
+		while let Some((_, other_height)) = blocking_trees.last() {
+			if *other_height >= height {
+				break;
+			}
+			blocking_trees.pop();
+		}
+
+		let distance = match blocking_trees.last() {
+			Some((blocking_position, _)) => position - blocking_position,
+			None => position,
+		};
+		record(row_index, col_index, distance);
+		blocking_trees.push((position, height));
+	}
+}
+
+fn viewing_distances(grid: &[Vec<u8>]) -> Vec<Vec<ViewingDistances>> {
+	let row_count = grid.len();
+	let col_count = grid[0].len();
+	let mut distances = vec![vec![ViewingDistances::default(); col_count]; row_count];
+
+	for row_index in 0..row_count {
+		record_viewing_distances(
+			grid,
+			(0..col_count).map(|col| (row_index, col)),
+			|row, col, distance| distances[row][col].left = distance,
+		);
+		record_viewing_distances(
+			grid,
+			(0..col_count).rev().map(|col| (row_index, col)),
+			|row, col, distance| distances[row][col].right = distance,
+		);
+	}
+
+	for col_index in 0..col_count {
+		record_viewing_distances(
+			grid,
+			(0..row_count).map(|row| (row, col_index)),
+			|row, col, distance| distances[row][col].up = distance,
+		);
+		record_viewing_distances(
+			grid,
+			(0..row_count).rev().map(|row| (row, col_index)),
+			|row, col, distance| distances[row][col].down = distance,
+		);
+	}
+
+	distances
+}
+
+fn write_csv<T: Display>(path: &str, matrix: &[Vec<T>]) -> io::Result<()> {
+	let mut file = BufWriter::new(File::create(path)?);
+	for row in matrix.iter() {
+		let line: Vec<String> = row.iter().map(|value| value.to_string()).collect();
+		writeln!(file, "{}", line.join(","))?;
+	}
+	file.flush()
+}
+
+fn write_ppm(path: &str, pixels: &[Vec<[u8; 3]>]) -> io::Result<()> {
+	let mut file = BufWriter::new(File::create(path)?);
+	let height = pixels.len();
+	let width = pixels.first().map_or(0, |row| row.len());
+	write!(file, "P6\n{} {}\n255\n", width, height)?;
+	for row in pixels.iter() {
+		for pixel in row.iter() {
+			file.write_all(pixel)?;
+		}
+	}
+	file.flush()
+}
+
+fn scenic_score_heatmap(scenic_scores: &[Vec<usize>], best_tree: (usize, usize)) -> Vec<Vec<[u8; 3]>> {
+	let highest_scenic_score = scenic_scores.iter().flatten().copied().max().unwrap_or(0).max(1) as f64;
+	let mut pixels: Vec<Vec<[u8; 3]>> = scenic_scores
+		.iter()
+		.map(|row| {
+			row.iter()
+				.map(|score| {
+					// Scores are products of four distances, so a square root keeps the lower scores from all being black
+					let brightness = ((*score as f64 / highest_scenic_score).sqrt() * 255.0).round() as u8;
+					[brightness, brightness, brightness]
+				})
+				.collect()
+		})
+		.collect();
+	pixels[best_tree.0][best_tree.1] = [255, 0, 0];
+	pixels
+}
 
 fn main() -> Result<(), Box<dyn Error>> {
+	let mut csv_path: Option<String> = None;
+	let mut ppm_path: Option<String> = None;
+	let mut args = env::args().skip(1);
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--csv" => csv_path = Some(args.next().ok_or("--csv requires an output path")?),
+			"--ppm" => ppm_path = Some(args.next().ok_or("--ppm requires an output path")?),
+			_ => return Err(format!("unknown argument: {}", arg).into()),
+		}
+	}
+
 	let grid: Vec<Vec<u8>> = {
 		let input_string = fs::read_to_string("input.txt")?;
 		let data: Vec<Vec<u8>> = input_string
@@ -11,52 +138,157 @@ fn main() -> Result<(), Box<dyn Error>> {
 		data
 	};
 
+	let distances = viewing_distances(&grid);
+
+	let scenic_scores: Vec<Vec<usize>> = distances
+		.iter()
+		.map(|row| row.iter().map(|tree| tree.scenic_score()).collect())
+		.collect();
+
+	let mut best_tree = (0, 0);
 	let mut highest_scenic_score = 0;
-	for (row_index, row) in grid.iter().enumerate() {
-		for (col_index, height) in row.iter().enumerate() {
-			let mut up_score = 0;
-			for other_row in grid.iter().take(row_index).rev() {
-				up_score += 1;
-				let other_height = other_row[col_index];
-				if other_height >= *height {
-					break;
-				}
+	for (row_index, row) in scenic_scores.iter().enumerate() {
+		for (col_index, scenic_score) in row.iter().enumerate() {
+			if *scenic_score > highest_scenic_score {
+				best_tree = (row_index, col_index);
+				highest_scenic_score = *scenic_score;
 			}
+		}
+	}
 
-			let mut left_score = 0;
-			for other_height in row.iter().take(col_index).rev() {
-				left_score += 1;
-				if *other_height >= *height {
-					break;
+	if let Some(path) = csv_path {
+		write_csv(&path, &scenic_scores)?;
+	}
+	if let Some(path) = ppm_path {
+		write_ppm(&path, &scenic_score_heatmap(&scenic_scores, best_tree))?;
+	}
+
+	println!("{}", highest_scenic_score);
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const SAMPLE: [&str; 5] = ["30373", "25512", "65332", "33549", "35390"];
+
+	struct Xorshift(u64);
+
+	impl Xorshift {
+		fn below(&mut self, limit: u64) -> u64 {
+			self.0 ^= self.0 << 13;
+			self.0 ^= self.0 >> 7;
+			self.0 ^= self.0 << 17;
+			self.0 % limit
+		}
+	}
+
+	fn parse_grid(rows: &[&str]) -> Vec<Vec<u8>> {
+		rows.iter().map(|row| row.bytes().map(|b| b - b'0').collect()).collect()
+	}
+
+	fn random_grid(rng: &mut Xorshift, row_count: usize, col_count: usize, max_height: u64) -> Vec<Vec<u8>> {
+		(0..row_count)
+			.map(|_| (0..col_count).map(|_| rng.below(max_height + 1) as u8).collect())
+			.collect()
+	}
+
+	// Includes single rows, single columns and grids where every tree has the same height
+	fn test_grids() -> Vec<Vec<Vec<u8>>> {
+		let mut rng = Xorshift(0x9e37_79b9_7f4a_7c15);
+		let mut grids = vec![parse_grid(&SAMPLE), vec![vec![5]], vec![vec![3; 9]; 7]];
+		for length in 1..=12 {
+			grids.push(random_grid(&mut rng, 1, length, 9));
+			grids.push(random_grid(&mut rng, length, 1, 9));
+		}
+		for _ in 0..200 {
+			let row_count = 1 + rng.below(15) as usize;
+			let col_count = 1 + rng.below(15) as usize;
+			let max_height = [0, 1, 3, 9][rng.below(4) as usize];
+			grids.push(random_grid(&mut rng, row_count, col_count, max_height));
+		}
+		grids
+	}
+
+	fn viewing_distances_brute_force(grid: &[Vec<u8>]) -> Vec<Vec<ViewingDistances>> {
+		let mut distances = Vec::with_capacity(grid.len());
+		for (row_index, row) in grid.iter().enumerate() {
+			let mut row_distances = Vec::with_capacity(row.len());
+			for (col_index, height) in row.iter().enumerate() {
+				let mut up_score = 0;
+				for other_row in grid.iter().take(row_index).rev() {
+					up_score += 1;
+					let other_height = other_row[col_index];
+					if other_height >= *height {
+						break;
+					}
 				}
-			}
 
-			let mut down_score = 0;
-			for other_row in grid.iter().skip(row_index + 1) {
-				down_score += 1;
-				let other_height = other_row[col_index];
-				if other_height >= *height {
-					break;
+				let mut left_score = 0;
+				for other_height in row.iter().take(col_index).rev() {
+					left_score += 1;
+					if *other_height >= *height {
+						break;
+					}
 				}
-			}
 
-			let mut right_score = 0;
-			for other_height in row.iter().skip(col_index + 1) {
-				right_score += 1;
-				if *other_height >= *height {
-					break;
+				let mut down_score = 0;
+				for other_row in grid.iter().skip(row_index + 1) {
+					down_score += 1;
+					let other_height = other_row[col_index];
+					if other_height >= *height {
+						break;
+					}
 				}
-			}
 
-			let scenic_score = up_score * left_score * down_score * right_score;
-			if scenic_score > highest_scenic_score {
-				println!("{} {}: {} {} {} {}", row_index, col_index, up_score, left_score, down_score, right_score);
-				highest_scenic_score = scenic_score;
+				let mut right_score = 0;
+				for other_height in row.iter().skip(col_index + 1) {
+					right_score += 1;
+					if *other_height >= *height {
+						break;
+					}
+				}
+
+				row_distances.push(ViewingDistances {
+					up: up_score,
+					left: left_score,
+					down: down_score,
+					right: right_score,
+				});
 			}
+			distances.push(row_distances);
 		}
+
+		distances
 	}
 
-	println!("{}", highest_scenic_score);
+	#[test]
+	fn sample_best_scenic_score() {
+		let distances = viewing_distances(&parse_grid(&SAMPLE));
+		assert_eq!(
+			distances[3][2],
+			ViewingDistances {
+				up: 2,
+				left: 2,
+				down: 1,
+				right: 2
+			}
+		);
+		let best = distances.iter().flatten().map(|tree| tree.scenic_score()).max();
+		assert_eq!(best, Some(8));
+	}
 
-	Ok(())
+	#[test]
+	fn viewing_distances_match_brute_force() {
+		for grid in test_grids() {
+			assert_eq!(
+				viewing_distances(&grid),
+				viewing_distances_brute_force(&grid),
+				"grid {:?}",
+				grid
+			);
+		}
+	}
 }
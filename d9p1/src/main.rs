@@ -1,50 +1,130 @@
-use std::cmp::Ordering;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::env;
 use std::error::Error;
+use std::fmt;
 use std::fs;
 use std::str::FromStr;
 
 enum Direction {
 	Up,
+	UpRight,
 	Right,
+	DownRight,
 	Down,
+	DownLeft,
 	Left,
+	UpLeft,
+}
+
+impl Direction {
+	fn offset(&self) -> (i32, i32) {
+		match self {
+			Self::Up => (0, -1),
+			Self::UpRight => (1, -1),
+			Self::Right => (1, 0),
+			Self::DownRight => (1, 1),
+			Self::Down => (0, 1),
+			Self::DownLeft => (-1, 1),
+			Self::Left => (-1, 0),
+			Self::UpLeft => (-1, -1),
+		}
+	}
 }
 
 impl FromStr for Direction {
-	type Err = ();
+	type Err = String;
 
 	fn from_str(s: &str) -> Result<Self, Self::Err> {
 		Ok(match s {
 			"U" => Self::Up,
+			"UR" => Self::UpRight,
 			"R" => Self::Right,
+			"DR" => Self::DownRight,
 			"D" => Self::Down,
+			"DL" => Self::DownLeft,
 			"L" => Self::Left,
-			_ => panic!("Unsupported direction: {}", s),
+			"UL" => Self::UpLeft,
+			_ => return Err(format!("Unsupported direction: {}", s)),
 		})
 	}
 }
 
+impl fmt::Display for Direction {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let direction = match self {
+			Self::Up => "U",
+			Self::UpRight => "UR",
+			Self::Right => "R",
+			Self::DownRight => "DR",
+			Self::Down => "D",
+			Self::DownLeft => "DL",
+			Self::Left => "L",
+			Self::UpLeft => "UL",
+		};
+		write!(f, "{}", direction)
+	}
+}
+
 struct Instruction {
 	direction: Direction,
 	distance: i32,
 }
 
 impl FromStr for Instruction {
-	type Err = ();
+	type Err = String;
 
 	fn from_str(s: &str) -> Result<Self, Self::Err> {
 		let mut parts = s.split(' ');
 		let direction = parts.next().unwrap();
-		let direction: Direction = direction.parse().unwrap();
+		let direction: Direction = direction.parse()?;
 
-		let distance = parts.next().unwrap();
-		let distance: i32 = distance.parse().unwrap();
+		let distance = parts.next().ok_or_else(|| format!("Missing distance: {}", s))?;
+		let distance: i32 = distance
+			.parse()
+			.map_err(|_| format!("Invalid distance: {}", distance))?;
 
 		Ok(Self { direction, distance })
 	}
 }
 
+impl fmt::Display for Instruction {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{} {}", self.direction, self.distance)
+	}
+}
+
+// Parses the run-length form of the movement log, where each direction is immediately followed by its distance (e.g.
+// "R4UR2D1"). Whitespace and commas may separate the runs.
+fn parse_compact_instructions(s: &str) -> Result<Vec<Instruction>, String> {
+	let mut instructions: Vec<Instruction> = Vec::new();
+	let mut chars = s.chars().filter(|c| !c.is_whitespace() && *c != ',').peekable();
+	while let Some(next_char) = chars.peek() {
+		if !next_char.is_ascii_alphabetic() {
+			return Err(format!("Expected a direction, found {}", next_char));
+		}
+
+		let mut direction = String::new();
+		while let Some(c) = chars.next_if(|c| c.is_ascii_alphabetic()) {
+			direction.push(c);
+		}
+		let direction: Direction = direction.parse()?;
+
+		let mut distance = String::new();
+		while let Some(c) = chars.next_if(|c| c.is_ascii_digit()) {
+			distance.push(c);
+		}
+		if distance.is_empty() {
+			return Err(format!("Missing distance after {}", direction));
+		}
+		let distance: i32 = distance
+			.parse()
+			.map_err(|_| format!("Invalid distance: {}", distance))?;
+
+		instructions.push(Instruction { direction, distance });
+	}
+	Ok(instructions)
+}
+
 #[derive(Clone, Default, Eq, Hash, PartialEq)]
 struct Coordinate {
 	x: i32,
@@ -53,66 +133,194 @@ struct Coordinate {
 
 impl Coordinate {
 	fn move_in_direction(&mut self, direction: &Direction) {
-		match direction {
-			Direction::Up => self.y -= 1,
-			Direction::Right => self.x += 1,
-			Direction::Down => self.y += 1,
-			Direction::Left => self.x -= 1,
-		}
+		let (x_offset, y_offset) = direction.offset();
+		self.x += x_offset;
+		self.y += y_offset;
 	}
 
 	fn move_toward(&mut self, other_coordinate: &Self) {
-		if self.x == other_coordinate.x {
-			if self.y < other_coordinate.y - 1 {
-				self.y += 1;
-			} else if self.y > other_coordinate.y + 1 {
-				self.y -= 1;
-			}
-		} else if self.y == other_coordinate.y {
-			if self.x < other_coordinate.x - 1 {
-				self.x += 1;
-			} else if self.x > other_coordinate.x + 1 {
-				self.x -= 1;
-			}
-		} else if self.x.abs_diff(other_coordinate.x) > 1 || self.y.abs_diff(other_coordinate.y) > 1 {
-			if self.x < other_coordinate.x {
-				self.x += 1;
-			} else {
-				self.x -= 1;
-			}
-			if self.y < other_coordinate.y {
-				self.y += 1;
-			} else {
-				self.y -= 1;
+		// Knots only move once they're no longer touching, and then move one step along each axis that differs
+		if self.x.abs_diff(other_coordinate.x) > 1 || self.y.abs_diff(other_coordinate.y) > 1 {
+			self.x += (other_coordinate.x - self.x).signum();
+			self.y += (other_coordinate.y - self.y).signum();
+		}
+	}
+}
+
+struct Rope {
+	knots: Vec<Coordinate>,
+	visited: Vec<HashSet<Coordinate>>,
+}
+
+impl Rope {
+	fn new(knot_count: usize) -> Self {
+		let knots = vec![Coordinate::default(); knot_count];
+		let visited = knots.iter().map(|knot| HashSet::from([knot.clone()])).collect();
+		Self { knots, visited }
+	}
+
+	fn step(&mut self, direction: &Direction) {
+		self.knots[0].move_in_direction(direction);
+		for knot_index in 1..self.knots.len() {
+			let previous_knot = self.knots[knot_index - 1].clone();
+			self.knots[knot_index].move_toward(&previous_knot);
+		}
+		for (knot, visited) in self.knots.iter().zip(self.visited.iter_mut()) {
+			visited.insert(knot.clone());
+		}
+	}
+
+	fn tail_visited(&self) -> &HashSet<Coordinate> {
+		self.visited.last().unwrap()
+	}
+
+	fn knot_label(&self, knot_index: usize) -> char {
+		if knot_index == 0 {
+			'H'
+		} else if self.knots.len() == 2 {
+			'T'
+		} else {
+			char::from_digit(knot_index as u32, 36).unwrap_or('#')
+		}
+	}
+
+	fn render(&self) -> String {
+		let start = Coordinate::default();
+		let seen_coordinates = || self.visited.iter().flatten().chain(std::iter::once(&start));
+		let min_x = seen_coordinates().map(|coord| coord.x).min().unwrap();
+		let max_x = seen_coordinates().map(|coord| coord.x).max().unwrap();
+		let min_y = seen_coordinates().map(|coord| coord.y).min().unwrap();
+		let max_y = seen_coordinates().map(|coord| coord.y).max().unwrap();
+
+		// Earlier knots are drawn over later ones, as in the puzzle illustrations
+		let mut labels: HashMap<&Coordinate, char> = HashMap::new();
+		for (knot_index, knot) in self.knots.iter().enumerate().rev() {
+			labels.insert(knot, self.knot_label(knot_index));
+		}
+
+		let mut output = String::new();
+		for y in min_y..=max_y {
+			for x in min_x..=max_x {
+				let coord = Coordinate { x, y };
+				let c = match labels.get(&coord) {
+					Some(label) => *label,
+					None if coord == start => 's',
+					None => '.',
+				};
+				output.push(c);
 			}
+			output.push('\n');
 		}
+		output
 	}
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
+	let mut knot_count = 2;
+	let mut trace = false;
+	let mut show_visits = false;
+	let mut compact = false;
+	let mut args = env::args().skip(1);
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--knots" => knot_count = args.next().ok_or("--knots requires a knot count")?.parse()?,
+			"--trace" => trace = true,
+			"--visits" => show_visits = true,
+			"--compact" => compact = true,
+			_ => return Err(format!("unknown argument: {}", arg).into()),
+		}
+	}
+	if knot_count == 0 {
+		return Err("a rope needs at least one knot".into());
+	}
+
 	let instructions = {
 		let input_string = fs::read_to_string("input.txt")?;
 
-		let mut instructions: Vec<Instruction> = Vec::new();
-		for instruction in input_string.lines().filter(|s| !s.is_empty()) {
-			instructions.push(instruction.parse().unwrap());
+		if compact {
+			parse_compact_instructions(&input_string)?
+		} else {
+			let mut instructions: Vec<Instruction> = Vec::new();
+			for instruction in input_string.lines().filter(|s| !s.is_empty()) {
+				instructions.push(instruction.parse()?);
+			}
+			instructions
 		}
-		instructions
 	};
 
-	let mut head_coordinate = Coordinate::default();
-	let mut tail_coordinate = Coordinate::default();
-	let mut tail_visited: HashSet<Coordinate> = HashSet::new();
+	let mut rope = Rope::new(knot_count);
+	if trace {
+		println!("== Initial State ==\n\n{}", rope.render());
+	}
 
 	for instruction in instructions {
+		if trace {
+			println!("== {} ==\n", instruction);
+		}
 		for _ in 0..instruction.distance {
-			head_coordinate.move_in_direction(&instruction.direction);
-			tail_coordinate.move_toward(&head_coordinate);
-			tail_visited.insert(tail_coordinate.clone());
+			rope.step(&instruction.direction);
+			if trace {
+				println!("{}", rope.render());
+			}
 		}
 	}
 
-	println!("{}", tail_visited.len());
+	if show_visits {
+		for (knot_index, visited) in rope.visited.iter().enumerate() {
+			println!("{}: {}", rope.knot_label(knot_index), visited.len());
+		}
+	}
+
+	println!("{}", rope.tail_visited().len());
 
 	Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const SAMPLE: &str = "R 4\nU 4\nL 3\nD 1\nR 4\nD 1\nL 5\nR 2\n";
+	const LARGER_SAMPLE: &str = "R 5\nU 8\nL 8\nD 3\nR 17\nD 10\nL 25\nU 20\n";
+
+	fn tail_visit_count(instructions: &[Instruction], knot_count: usize) -> usize {
+		let mut rope = Rope::new(knot_count);
+		for instruction in instructions.iter() {
+			for _ in 0..instruction.distance {
+				rope.step(&instruction.direction);
+			}
+		}
+		rope.tail_visited().len()
+	}
+
+	#[test]
+	fn sample_tail_visits_in_both_formats() {
+		for (input, knot_count, expected) in [(SAMPLE, 2, 13), (SAMPLE, 10, 1), (LARGER_SAMPLE, 10, 36)] {
+			let instructions: Vec<Instruction> = input.lines().map(|line| line.parse().unwrap()).collect();
+			let compact_input: String = input.split_whitespace().collect();
+			let compact_instructions = parse_compact_instructions(&compact_input).unwrap();
+			assert_eq!(tail_visit_count(&instructions, knot_count), expected);
+			assert_eq!(tail_visit_count(&compact_instructions, knot_count), expected);
+		}
+	}
+
+	fn positions(rope: &Rope) -> Vec<(i32, i32)> {
+		rope.knots.iter().map(|knot| (knot.x, knot.y)).collect()
+	}
+
+	// Once the head is two steps away along both axes, the next knot has to follow it diagonally
+	#[test]
+	fn diagonal_head_moves() {
+		let instructions = parse_compact_instructions("UR2, DL1").unwrap();
+		let mut rope = Rope::new(3);
+		rope.step(&instructions[0].direction);
+		assert_eq!(positions(&rope), vec![(1, -1), (0, 0), (0, 0)]);
+		rope.step(&instructions[0].direction);
+		assert_eq!(positions(&rope), vec![(2, -2), (1, -1), (0, 0)]);
+		rope.step(&instructions[0].direction);
+		assert_eq!(positions(&rope), vec![(3, -3), (2, -2), (1, -1)]);
+		rope.step(&instructions[1].direction);
+		assert_eq!(positions(&rope), vec![(2, -2), (2, -2), (1, -1)]);
+		assert_eq!(rope.tail_visited().len(), 2);
+	}
+}